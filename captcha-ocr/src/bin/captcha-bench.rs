@@ -0,0 +1,163 @@
+//! Measures how often a recognizer reads a directory of labelled captchas
+//! right, per image and per character, for one or more voting settings:
+//!
+//!     cargo run -r -p captcha-ocr --bin captcha-bench -- captcha-ocr/samples/test --threshold 2,3,4
+//!
+//! Options:
+//!   --threshold <n,...>   frames a pixel has to be drawn in, each value is evaluated (3)
+//!   --white <level>       gray level from which a pixel counts as background (255)
+//!   --recognizer <name>   `native` or, with the `tesseract` feature, `tesseract` (native)
+//!   --templates <file>    templates for the native recognizer instead of the built-in ones
+//!   --verbose             lists every misread image
+
+use captcha_ocr::{frames, samples, Recognizer};
+use captcha_ocr::template::TemplateRecognizer;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+struct Options {
+	dir: PathBuf,
+	thresholds: Vec<usize>,
+	white: u8,
+	recognizer: String,
+	templates: Option<PathBuf>,
+	verbose: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+	let mut args = env::args().skip(1);
+	let mut options = Options {
+		dir: PathBuf::new(),
+		thresholds: vec![frames::Voting::default().threshold],
+		white: frames::Voting::default().white,
+		recognizer: "native".to_string(),
+		templates: None,
+		verbose: false,
+	};
+	let mut dir = None;
+	while let Some(arg) = args.next() {
+		let mut value = |name: &str| args.next().ok_or(format!("Missing value of {}", name));
+		match arg.as_str() {
+			"--threshold" => options.thresholds = value(&arg)?.split(',')
+				.map(|t| t.parse().map_err(|_| format!("Invalid threshold: {}", t)))
+				.collect::<Result<_, _>>()?,
+			"--white" => options.white = value(&arg)?.parse().map_err(|_| "Invalid white level".to_string())?,
+			"--recognizer" => options.recognizer = value(&arg)?,
+			"--templates" => options.templates = Some(PathBuf::from(value(&arg)?)),
+			"--verbose" => options.verbose = true,
+			_ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+			_ => dir = Some(PathBuf::from(arg)),
+		}
+	}
+	options.dir = dir.ok_or("Usage: captcha-bench <samples directory> [options]")?;
+	Ok(options)
+}
+
+fn recognizer(options: &Options) -> Result<Box<dyn Recognizer>, Box<dyn std::error::Error>> {
+	match options.recognizer.as_str() {
+		"native" => match &options.templates {
+			Some(path) => Ok(Box::new(TemplateRecognizer::parse(&fs::read_to_string(path)?).ok_or("Malformed templates")?)),
+			None => Ok(Box::new(TemplateRecognizer::default())),
+		},
+		#[cfg(feature = "tesseract")]
+		"tesseract" => Ok(Box::new(captcha_ocr::tesseract::TesseractRecognizer)),
+		name => Err(format!("Unknown recognizer: {}", name).into()),
+	}
+}
+
+#[derive(Default)]
+struct Tally {
+	right: usize,
+	total: usize,
+}
+
+impl Tally {
+	fn add(&mut self, right: bool) {
+		self.right += right as usize;
+		self.total += 1;
+	}
+}
+
+impl std::fmt::Display for Tally {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let rate = if self.total == 0 { 0.0 } else { 100.0 * self.right as f64 / self.total as f64 };
+		write!(f, "{:>5}/{:<5} {:6.2}%", self.right, self.total, rate)
+	}
+}
+
+#[derive(Default)]
+struct Report {
+	images: Tally,
+	characters: Tally,
+	positions: Vec<Tally>,
+	/// Per expected character, its tally and what it was misread as.
+	classes: BTreeMap<char, (Tally, BTreeMap<char, usize>)>,
+	/// Readings that are not 4 letters or digits, rejected before submitting.
+	malformed: usize,
+	elapsed: Duration,
+}
+
+fn evaluate(samples: &[samples::Sample], voting: frames::Voting, recognizer: &dyn Recognizer, verbose: bool) -> Result<Report, Box<dyn std::error::Error>> {
+	let mut report = Report::default();
+	for sample in samples {
+		let start = Instant::now();
+		let mask = frames::merge(&sample.gif, voting)?;
+		let captcha = recognizer.recognize(&mask)?;
+		report.elapsed += start.elapsed();
+
+		report.images.add(captcha.text == sample.label);
+		report.malformed += !captcha.is_well_formed() as usize;
+		if verbose && captcha.text != sample.label {
+			println!("  {} read as {:?} ({})", sample.label, captcha.text, captcha.confidence);
+		}
+		let mut read = captcha.text.chars();
+		for (i, expected) in sample.label.chars().enumerate() {
+			let got = read.next();
+			let right = got == Some(expected);
+			report.characters.add(right);
+			if report.positions.len() <= i {
+				report.positions.push(Tally::default());
+			}
+			report.positions[i].add(right);
+			let (tally, misreads) = report.classes.entry(expected).or_default();
+			tally.add(right);
+			if !right {
+				*misreads.entry(got.unwrap_or('_')).or_default() += 1;
+			}
+		}
+	}
+	Ok(report)
+}
+
+fn print(report: &Report, samples: usize) {
+	println!("  images      {}", report.images);
+	println!("  characters  {}", report.characters);
+	for (i, tally) in report.positions.iter().enumerate() {
+		println!("  position {}  {}", i + 1, tally);
+	}
+	println!("  malformed   {:>5}", report.malformed);
+	println!("  per character:");
+	for (c, (tally, misreads)) in &report.classes {
+		let misreads: Vec<String> = misreads.iter().map(|(m, n)| format!("{}×{}", m, n)).collect();
+		println!("    {}  {}  {}", c, tally, misreads.join(" "));
+	}
+	if samples > 0 {
+		println!("  mean time   {:.2} ms", report.elapsed.as_secs_f64() * 1000.0 / samples as f64);
+	}
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let options = parse_args()?;
+	let samples = samples::load(&options.dir)?;
+	let recognizer = recognizer(&options)?;
+	println!("{} samples from {}, {} recognizer", samples.len(), options.dir.display(), options.recognizer);
+	for &threshold in &options.thresholds {
+		let voting = frames::Voting { threshold, white: options.white };
+		println!("threshold {}, white {}", voting.threshold, voting.white);
+		let report = evaluate(&samples, voting, recognizer.as_ref(), options.verbose)?;
+		print(&report, samples.len());
+	}
+	Ok(())
+}
@@ -0,0 +1,21 @@
+//! Trains the templates of the native recognizer from a directory of
+//! labelled captchas:
+//!
+//!     cargo run -p captcha-ocr --bin captcha-train -- captcha-ocr/samples/train > captcha-ocr/src/templates.txt
+
+use captcha_ocr::{frames, samples};
+use captcha_ocr::template::TemplateRecognizer;
+use std::env;
+use std::path::Path;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let dir = env::args().nth(1).ok_or("Usage: captcha-train <samples directory>")?;
+	let mut masks = Vec::new();
+	for sample in samples::load(Path::new(&dir))? {
+		masks.push((sample.label, frames::merge(&sample.gif, frames::Voting::default())?));
+	}
+	eprintln!("Training on {} samples", masks.len());
+	let recognizer = TemplateRecognizer::train(masks.iter().map(|(l, m)| (l.as_str(), m)));
+	print!("{}", recognizer.to_text());
+	Ok(())
+}
@@ -0,0 +1,45 @@
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, DynamicImage, GrayImage, ImageError, Luma};
+use std::io::Cursor;
+
+pub const WIDTH: u32 = 90;
+pub const HEIGHT: u32 = 58;
+
+/// Parameters of the frame voting.
+#[derive(Clone, Copy, Debug)]
+pub struct Voting {
+	/// Frames a pixel has to be drawn in to count as text.
+	pub threshold: usize,
+	/// Gray level from which a pixel counts as background.
+	pub white: u8,
+}
+
+impl Default for Voting {
+	fn default() -> Self {
+		Self { threshold: 3, white: 255 }
+	}
+}
+
+/// Merges the frames of a captcha into a mask where the pixels drawn in at
+/// least `voting.threshold` frames are white, the speckles of single frames
+/// being dropped.
+pub fn merge(gif: &[u8], voting: Voting) -> Result<GrayImage, ImageError> {
+	let g = GifDecoder::new(Cursor::new(gif))?;
+	let frames: Vec<_> = g.into_frames().collect::<Result<_, _>>()?;
+	let grayscale_frames: Vec<_> = frames.iter().map(|f| DynamicImage::ImageRgba8(f.buffer().clone()).into_luma8()).collect();
+	let mut img = GrayImage::new(WIDTH, HEIGHT);
+	for w in 0..WIDTH {
+		for h in 0..HEIGHT {
+			let mut cnt = 0;
+			for frame in &grayscale_frames {
+				if w < frame.width() && h < frame.height() && frame.get_pixel(w, h)[0] < voting.white {
+					cnt += 1;
+				}
+			}
+			if cnt >= voting.threshold {
+				img.put_pixel(w, h, Luma([255u8]));
+			}
+		}
+	}
+	Ok(img)
+}
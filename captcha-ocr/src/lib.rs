@@ -0,0 +1,34 @@
+//! Recognition of the CAS captcha: an animated GIF whose frames all show the
+//! same 4 characters over changing speckles.
+
+pub mod frames;
+pub mod samples;
+pub mod template;
+#[cfg(feature = "tesseract")]
+pub mod tesseract;
+
+use image::GrayImage;
+
+/// A captcha as read by a recognizer, `confidence` going from 0 to 100.
+pub struct Captcha {
+	pub text: String,
+	pub confidence: i32,
+}
+
+impl Captcha {
+	/// CAS captchas are always 4 letters or digits, anything else is a misread.
+	pub fn is_well_formed(&self) -> bool {
+		self.text.len() == 4 && self.text.chars().all(|c| c.is_ascii_alphanumeric())
+	}
+}
+
+pub trait Recognizer: Send + Sync {
+	/// Reads the text of a mask produced by `frames::merge`, text pixels being white.
+	fn recognize(&self, mask: &GrayImage) -> Result<Captcha, Box<dyn std::error::Error>>;
+}
+
+/// Reads a captcha GIF, merging its frames with the default voting.
+pub fn decode(gif: &[u8], recognizer: &dyn Recognizer) -> Result<Captcha, Box<dyn std::error::Error>> {
+	let mask = frames::merge(gif, frames::Voting::default())?;
+	recognizer.recognize(&mask)
+}
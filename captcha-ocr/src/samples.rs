@@ -0,0 +1,31 @@
+//! Labelled captchas, stored as GIFs named after their text, e.g. `4821.gif`
+//! or `4821_2.gif` for a second sample with the same text.
+//!
+//! `samples/train` is what the templates are trained on, `samples/test` is
+//! held out to measure them.
+
+use std::path::Path;
+use std::fs;
+
+pub struct Sample {
+	pub label: String,
+	pub gif: Vec<u8>,
+}
+
+/// Text of a sample, the part of its file name before any `_` or `.`.
+pub fn label(name: &str) -> &str {
+	name.split(['_', '.']).next().unwrap_or(name)
+}
+
+/// Loads the GIFs of `dir`, sorted by file name.
+pub fn load(dir: &Path) -> std::io::Result<Vec<Sample>> {
+	let mut paths: Vec<_> = fs::read_dir(dir)?
+		.map(|e| e.map(|e| e.path()))
+		.collect::<Result<_, _>>()?;
+	paths.retain(|p| p.extension().is_some_and(|e| e == "gif"));
+	paths.sort();
+	paths.into_iter().map(|path| {
+		let name = path.file_name().unwrap().to_string_lossy().to_string();
+		Ok(Sample { label: label(&name).to_string(), gif: fs::read(&path)? })
+	}).collect()
+}
@@ -0,0 +1,203 @@
+//! Native recognizer: splits the mask into one glyph per character and
+//! matches each against templates averaged from labelled samples.
+//!
+//! Experimental: the samples shipped are rendered by `mock-upstream`, so
+//! the templates know its digits only and have never seen a CAS captcha.
+
+use image::GrayImage;
+use std::fmt::Write as _;
+use super::{Captcha, Recognizer};
+
+/// Size glyphs are scaled to before being compared.
+const GLYPH_WIDTH: usize = 10;
+const GLYPH_HEIGHT: usize = 14;
+/// Characters per captcha.
+const LENGTH: usize = 4;
+/// Templates trained from `samples/train`, see `captcha-train`.
+const TEMPLATES: &str = include_str!("templates.txt");
+
+#[derive(Clone, PartialEq)]
+pub struct Glyph([bool; GLYPH_WIDTH * GLYPH_HEIGHT]);
+
+impl Glyph {
+	/// Fraction of pixels that differ from `other`.
+	fn distance(&self, other: &Glyph) -> f64 {
+		self.0.iter().zip(other.0.iter()).filter(|(a, b)| a != b).count() as f64 / self.0.len() as f64
+	}
+}
+
+/// Columns `[start, end)` of the mask holding each character. Runs of text
+/// columns are taken as characters; runs too thin to be one are dropped and
+/// the widest run is halved while characters touch each other.
+fn columns(mask: &GrayImage) -> Vec<(u32, u32)> {
+	let filled = |x: u32| (0..mask.height()).any(|y| mask.get_pixel(x, y)[0] != 0);
+	let mut runs = Vec::new();
+	let mut start = None;
+	for x in 0..=mask.width() {
+		match (start, x < mask.width() && filled(x)) {
+			(None, true) => start = Some(x),
+			(Some(s), false) => {
+				if x - s >= 2 {
+					runs.push((s, x));
+				}
+				start = None;
+			},
+			_ => {},
+		}
+	}
+	while !runs.is_empty() && runs.len() < LENGTH {
+		let (i, &(s, e)) = runs.iter().enumerate().max_by_key(|(_, (s, e))| e - s).unwrap();
+		if e - s < 2 {
+			break;
+		}
+		let mid = s + (e - s) / 2;
+		runs.splice(i..=i, [(s, mid), (mid, e)]);
+	}
+	while runs.len() > LENGTH {
+		let (i, _) = runs.iter().enumerate().min_by_key(|(_, (s, e))| e - s).unwrap();
+		runs.remove(i);
+	}
+	runs
+}
+
+/// Crops the text of columns `[start, end)` and scales it to the glyph size.
+fn glyph(mask: &GrayImage, (start, end): (u32, u32)) -> Glyph {
+	let rows: Vec<u32> = (0..mask.height()).filter(|&y| (start..end).any(|x| mask.get_pixel(x, y)[0] != 0)).collect();
+	let (top, bottom) = (rows.first().copied().unwrap_or(0), rows.last().copied().unwrap_or(0) + 1);
+	let mut pixels = [false; GLYPH_WIDTH * GLYPH_HEIGHT];
+	for gy in 0..GLYPH_HEIGHT {
+		for gx in 0..GLYPH_WIDTH {
+			let x = start + (gx as u32 * (end - start)) / GLYPH_WIDTH as u32;
+			let y = top + (gy as u32 * (bottom - top)) / GLYPH_HEIGHT as u32;
+			pixels[gy * GLYPH_WIDTH + gx] = mask.get_pixel(x, y)[0] != 0;
+		}
+	}
+	Glyph(pixels)
+}
+
+/// Splits a mask into its character glyphs, left to right.
+pub fn segment(mask: &GrayImage) -> Vec<Glyph> {
+	columns(mask).into_iter().map(|c| glyph(mask, c)).collect()
+}
+
+pub struct TemplateRecognizer {
+	templates: Vec<(char, Glyph)>,
+}
+
+impl Default for TemplateRecognizer {
+	fn default() -> Self {
+		Self::parse(TEMPLATES).expect("Malformed captcha templates")
+	}
+}
+
+impl TemplateRecognizer {
+	/// Averages the glyphs of labelled masks into one template per character.
+	/// Masks that do not split into as many glyphs as their label has
+	/// characters are skipped.
+	pub fn train<'a>(samples: impl IntoIterator<Item = (&'a str, &'a GrayImage)>) -> Self {
+		let mut sums: Vec<(char, Vec<u32>, u32)> = Vec::new();
+		for (label, mask) in samples {
+			let glyphs = segment(mask);
+			if glyphs.len() != label.chars().count() {
+				continue;
+			}
+			for (c, g) in label.chars().zip(glyphs) {
+				let i = match sums.iter().position(|(sc, _, _)| *sc == c) {
+					Some(i) => i,
+					None => {
+						sums.push((c, vec![0; GLYPH_WIDTH * GLYPH_HEIGHT], 0));
+						sums.len() - 1
+					},
+				};
+				for (sum, &p) in sums[i].1.iter_mut().zip(g.0.iter()) {
+					*sum += p as u32;
+				}
+				sums[i].2 += 1;
+			}
+		}
+		sums.sort_by_key(|(c, _, _)| *c);
+		let templates = sums.into_iter().map(|(c, sum, n)| {
+			let mut pixels = [false; GLYPH_WIDTH * GLYPH_HEIGHT];
+			for (p, s) in pixels.iter_mut().zip(sum) {
+				*p = s * 2 >= n;
+			}
+			(c, Glyph(pixels))
+		}).collect();
+		Self { templates }
+	}
+
+	/// Reads templates written by `to_text`.
+	pub fn parse(text: &str) -> Option<Self> {
+		let mut templates = Vec::new();
+		let mut lines = text.lines().filter(|l| !l.is_empty() && !l.starts_with('#'));
+		while let Some(header) = lines.next() {
+			let c = header.strip_prefix("= ")?.chars().next()?;
+			let mut pixels = [false; GLYPH_WIDTH * GLYPH_HEIGHT];
+			for y in 0..GLYPH_HEIGHT {
+				let row = lines.next()?;
+				if row.len() != GLYPH_WIDTH {
+					return None;
+				}
+				for (x, p) in row.chars().enumerate() {
+					pixels[y * GLYPH_WIDTH + x] = p == 'X';
+				}
+			}
+			templates.push((c, Glyph(pixels)));
+		}
+		Some(Self { templates })
+	}
+
+	/// One `= <char>` line per template followed by its rows, `X` marking text.
+	pub fn to_text(&self) -> String {
+		let mut text = String::new();
+		for (c, g) in &self.templates {
+			writeln!(text, "= {}", c).unwrap();
+			for row in g.0.chunks(GLYPH_WIDTH) {
+				writeln!(text, "{}", row.iter().map(|&p| if p { 'X' } else { '.' }).collect::<String>()).unwrap();
+			}
+		}
+		text
+	}
+}
+
+impl Recognizer for TemplateRecognizer {
+	fn recognize(&self, mask: &GrayImage) -> Result<Captcha, Box<dyn std::error::Error>> {
+		let mut text = String::new();
+		let mut distance = 0.0;
+		for g in segment(mask) {
+			let (c, d) = self.templates.iter()
+				.map(|(c, t)| (*c, g.distance(t)))
+				.min_by(|a, b| a.1.total_cmp(&b.1))
+				.ok_or("No captcha templates")?;
+			text.push(c);
+			distance += d;
+		}
+		let confidence = if text.is_empty() { 0 } else { (100.0 * (1.0 - distance / text.len() as f64)) as i32 };
+		Ok(Captcha { text, confidence })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{decode, samples};
+	use std::path::Path;
+
+	/// Share of the held-out captchas the built-in templates must read right.
+	const MIN_ACCURACY: f64 = 0.95;
+
+	/// A regression check of merging and matching on the held-out mock
+	/// captchas, not a measure of how CAS captchas are read.
+	#[test]
+	fn reads_held_out_mock_samples() {
+		let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/test");
+		let samples = samples::load(&dir).unwrap();
+		assert!(!samples.is_empty(), "no samples in {}", dir.display());
+		let recognizer = TemplateRecognizer::default();
+		let right = samples.iter()
+			.filter(|s| decode(&s.gif, &recognizer).unwrap().text == s.label)
+			.count();
+		let accuracy = right as f64 / samples.len() as f64;
+		assert!(accuracy >= MIN_ACCURACY, "{}/{} held-out captchas read right", right, samples.len());
+	}
+}
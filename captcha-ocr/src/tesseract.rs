@@ -0,0 +1,17 @@
+use image::GrayImage;
+use tesseract::Tesseract;
+use super::{Captcha, Recognizer};
+
+/// Reads captchas with libtesseract's English model.
+pub struct TesseractRecognizer;
+
+impl Recognizer for TesseractRecognizer {
+	fn recognize(&self, mask: &GrayImage) -> Result<Captcha, Box<dyn std::error::Error>> {
+		let (width, height) = (mask.width() as i32, mask.height() as i32);
+		let mut tess = Tesseract::new(None, Some("eng"))?
+			.set_frame(mask.as_raw(), width, height, 1, width)?
+			.recognize()?;
+		let text = tess.get_text()?.chars().filter(|c| !c.is_whitespace()).collect();
+		Ok(Captcha { text, confidence: tess.mean_text_conf() })
+	}
+}
@@ -0,0 +1,203 @@
+//! Rules tagging the merchants that have no tag of their own, from their name
+//! or merchant account.
+//!
+//! Rules are a JSON list, kept under `keys::RULES` in the tags database and
+//! seeded from `rules.json`:
+//!
+//! ```json
+//! [
+//!     { "tag": "GRO", "priority": 10, "mercacc": [1001000, 1001999] },
+//!     { "tag": "CAF", "contains": ["食堂", "百景"] },
+//!     { "tag": "LOG", "regex": "^(校园)?洗衣" }
+//! ]
+//! ```
+//!
+//! A rule matches when every condition it sets does: `contains` when the name
+//! contains one of its strings, `regex` when it matches the name, `mercacc`
+//! when the account lies in the range, bounds included. The first matching
+//! rule wins, rules of a higher `priority` being tried first. A rule may set a
+//! subcategory, as in `CAF/百景`.
+
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use crate::model::category::{self, SEPARATOR, TAGS};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClassifierError {
+    #[error("Unreadable rules: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Rule {0}: unknown tag {1}")]
+    UnknownTag(usize, String),
+    #[error("Rule {0}: invalid regex: {1}")]
+    InvalidRegex(usize, regex::Error),
+    #[error("Rule {0}: empty merchant account range")]
+    EmptyRange(usize),
+    #[error("Rule {0}: no condition")]
+    NoCondition(usize),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rule {
+    pub tag: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contains: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mercacc: Option<(u64, u64)>,
+}
+
+struct Compiled {
+    rule: Rule,
+    regex: Option<Regex>,
+}
+
+impl Compiled {
+    fn matches(&self, mercacc: Option<u64>, mercname: &str) -> bool {
+        if !self.rule.contains.is_empty() && !self.rule.contains.iter().any(|s| mercname.contains(s.as_str())) {
+            return false;
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(mercname) {
+                return false;
+            }
+        }
+        if let Some((from, to)) = self.rule.mercacc {
+            match mercacc {
+                Some(mercacc) if from <= mercacc && mercacc <= to => {},
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Rules checked, in the order they are tried.
+#[derive(Default)]
+pub struct Classifier {
+    rules: Vec<Compiled>,
+}
+
+impl Classifier {
+    /// Checks and compiles `rules`. Rules of the same priority keep their order.
+    pub fn new(rules: Vec<Rule>) -> Result<Classifier, ClassifierError> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for (i, rule) in rules.into_iter().enumerate() {
+            if !TAGS.contains(&category::top(&rule.tag)) || rule.tag.split(SEPARATOR).any(str::is_empty) {
+                return Err(ClassifierError::UnknownTag(i, rule.tag));
+            }
+            if let Some((from, to)) = rule.mercacc {
+                if from > to {
+                    return Err(ClassifierError::EmptyRange(i));
+                }
+            }
+            if rule.contains.is_empty() && rule.regex.is_none() && rule.mercacc.is_none() {
+                return Err(ClassifierError::NoCondition(i));
+            }
+            let regex = match &rule.regex {
+                Some(regex) => Some(Regex::new(regex).map_err(|e| ClassifierError::InvalidRegex(i, e))?),
+                None => None,
+            };
+            compiled.push(Compiled { rule, regex });
+        }
+        compiled.sort_by_key(|c| std::cmp::Reverse(c.rule.priority));
+        Ok(Classifier { rules: compiled })
+    }
+
+    /// Reads rules from their JSON list.
+    pub fn from_json(json: &str) -> Result<Classifier, ClassifierError> {
+        Classifier::new(serde_json::from_str(json)?)
+    }
+
+    /// The tag of the first rule matching the merchant, if any.
+    pub fn classify(&self, mercacc: &str, mercname: &str) -> Option<&str> {
+        let mercacc = mercacc.parse().ok();
+        self.rules.iter()
+            .find(|rule| rule.matches(mercacc, mercname))
+            .map(|rule| rule.rule.tag.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier(json: &str) -> Classifier {
+        Classifier::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        let classifier = classifier(r#"[
+            { "tag": "CAF", "contains": ["超市"] },
+            { "tag": "GRO", "priority": 10, "contains": ["超市"] },
+            { "tag": "LOG", "priority": -1, "contains": ["超市"] }
+        ]"#);
+        assert_eq!(classifier.classify("1001", "东区超市"), Some("GRO"));
+    }
+
+    #[test]
+    fn same_priority_keeps_order() {
+        let classifier = classifier(r#"[
+            { "tag": "LOG", "priority": 5, "contains": ["洗衣"] },
+            { "tag": "CAF/百景", "contains": ["食堂"] },
+            { "tag": "OTH", "contains": ["食堂"] },
+            { "tag": "GRO", "priority": 5, "contains": ["百景园"] },
+            { "tag": "CAF", "priority": 5, "contains": ["百景园"] }
+        ]"#);
+        assert_eq!(classifier.classify("1001", "百景园"), Some("GRO"));
+        assert_eq!(classifier.classify("1001", "百景园洗衣"), Some("LOG"));
+        assert_eq!(classifier.classify("1001", "百景食堂"), Some("CAF/百景"));
+    }
+
+    #[test]
+    fn every_condition_must_match() {
+        let classifier = classifier(r#"[
+            { "tag": "LOG/laundry", "contains": ["洗衣", "洗涤"], "regex": "^(校园)?洗", "mercacc": [2000, 2999] }
+        ]"#);
+        assert_eq!(classifier.classify("2500", "校园洗衣房"), Some("LOG/laundry"));
+        assert_eq!(classifier.classify("2000", "洗涤中心"), Some("LOG/laundry"));
+        assert_eq!(classifier.classify("2999", "洗衣"), Some("LOG/laundry"));
+        // One condition failing each.
+        assert_eq!(classifier.classify("2500", "校园洗车"), None);
+        assert_eq!(classifier.classify("2500", "东区洗衣房"), None);
+        assert_eq!(classifier.classify("3000", "校园洗衣房"), None);
+        assert_eq!(classifier.classify("A2500", "校园洗衣房"), None);
+        assert_eq!(classifier.classify("1001", "食堂"), None);
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(matches!(Classifier::from_json(r#"[{ "tag": "CAF" "#), Err(ClassifierError::Parse(_))));
+        assert!(matches!(Classifier::from_json(r#"[{ "priority": 1, "contains": ["x"] }]"#), Err(ClassifierError::Parse(_))));
+    }
+
+    #[test]
+    fn unknown_tag() {
+        for tag in ["FOO", "CAF/", "CAF//百景", ""] {
+            let json = format!(r#"[{{ "tag": "CAF", "contains": ["x"] }}, {{ "tag": "{}", "contains": ["x"] }}]"#, tag);
+            assert!(matches!(Classifier::from_json(&json), Err(ClassifierError::UnknownTag(1, t)) if t == tag), "{:?}", tag);
+        }
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let error = Classifier::from_json(r#"[{ "tag": "CAF", "regex": "(食堂" }]"#);
+        assert!(matches!(error, Err(ClassifierError::InvalidRegex(0, _))));
+    }
+
+    #[test]
+    fn empty_range() {
+        let error = Classifier::from_json(r#"[{ "tag": "GRO", "mercacc": [1001999, 1001000] }]"#);
+        assert!(matches!(error, Err(ClassifierError::EmptyRange(0))));
+        assert!(Classifier::from_json(r#"[{ "tag": "GRO", "mercacc": [1001000, 1001000] }]"#).is_ok());
+    }
+
+    #[test]
+    fn no_condition() {
+        let error = Classifier::from_json(r#"[{ "tag": "CAF", "contains": ["x"] }, { "tag": "OTH", "priority": 3, "contains": [] }]"#);
+        assert!(matches!(error, Err(ClassifierError::NoCondition(1))));
+    }
+}
@@ -0,0 +1,157 @@
+use config_file::FromConfigFile;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::env;
+
+const CAS_URL: &str = "https://pass.hust.edu.cn";
+const ECARD_URL: &str = "http://ecard.m.hust.edu.cn";
+
+#[derive(Deserialize, Clone)]
+pub struct Database {
+    pub url: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Redis {
+    pub url: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TagsDB {
+    pub url: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct UntaggedDB {
+    pub url: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Queue {
+    /// Seconds a job may stay claimed by a worker that stopped reporting in
+    /// before it is handed to another one.
+    pub visibility_timeout: u64,
+    /// Attempts before a job is moved to the dead-letter list.
+    pub max_attempts: u32,
+    /// Delay before the first retry in seconds, doubled on every further attempt.
+    pub retry_backoff: u64,
+    /// Jobs processed at the same time. Jobs of the same account always run
+    /// one after another.
+    pub concurrency: usize,
+    /// Seconds a job may run before it is stopped and failed as a retryable
+    /// error.
+    pub job_timeout: u64,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self {
+            visibility_timeout: 600,
+            max_attempts: 5,
+            retry_backoff: 10,
+            concurrency: 4,
+            job_timeout: 900,
+        }
+    }
+}
+
+/// Base URLs of CAS and the card system, for staging mirrors or `mock-upstream`.
+/// Unset fields fall back to `HUST_CAS_URL` and `HUST_ECARD_URL`, then to the
+/// live hosts.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Upstream {
+    /// CAS host, serving `/cas/login`, `/cas/rsa` and `/cas/code`.
+    pub cas: String,
+    /// Card system host, serving `/wechat-web/QueryController/*`.
+    pub ecard: String,
+}
+
+impl Default for Upstream {
+    fn default() -> Self {
+        Self {
+            cas: env::var("HUST_CAS_URL").unwrap_or(CAS_URL.to_string()),
+            ecard: env::var("HUST_ECARD_URL").unwrap_or(ECARD_URL.to_string()),
+        }
+    }
+}
+
+impl Upstream {
+    pub fn cas_url(&self, path: &str) -> String {
+        format!("{}{}", self.cas.trim_end_matches('/'), path)
+    }
+
+    pub fn ecard_url(&self, path: &str) -> String {
+        format!("{}{}", self.ecard.trim_end_matches('/'), path)
+    }
+}
+
+/// Keys the CASTGC is encrypted with before it is written to Redis. New
+/// credentials are sealed with `current`, every key listed can open them.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Credentials {
+    /// Id of the key to seal with.
+    pub current: String,
+    /// 32 byte keys, base64 encoded, by id.
+    pub keys: HashMap<String, String>,
+}
+
+pub fn init_config_from_file<C: DeserializeOwned>(path: &str) -> Result<C, Box<dyn std::error::Error>> {
+    match C::from_config_file(path){
+		Ok(config) => Ok(config),
+		Err(e) => Err(Box::new(e)),
+	}
+}
+
+pub fn init_config_from_str<C: DeserializeOwned>(text: &str) -> Result<C, Box<dyn std::error::Error>> {
+    match toml::from_str(text){
+        Ok(config) => Ok(config),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// Loads the configuration from the config center when `APP_NAME` is set and
+/// from `config.toml` otherwise. Each binary picks the sections it needs.
+pub async fn init_config<C: DeserializeOwned>() -> C {
+    match env::var_os("APP_NAME"){
+        Some(val) => {
+            match reqwest::get(format!("http://cc-server.config-center/{}/config.toml/raw", val.into_string().unwrap())).await{
+                Ok(res) => {
+                    match res.text().await{
+                        Ok(text) => {
+                            match init_config_from_str(text.as_str()) {
+                                Ok(config) => config,
+                                Err(e) => {
+                                    panic!("Failed to load config: {}", e);
+                                },
+                            }
+                        },
+                        Err(e) => {
+                            panic!("Failed to load config: {}", e);
+                        }
+                    }
+                },
+                Err(e) => {
+                    panic!("Failed to load config from config-center: {}", e);
+                }
+            }
+        },
+        None => {
+            match init_config_from_file("config.toml") {
+                Ok(config) => config,
+                Err(e) => {
+                    panic!("Failed to load config from file: {}", e);
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Client of the card system (`ecard.m.hust.edu.cn`), reached through CAS
+//! with the CASTGC of a logged in user.
+
+use reqwest::{Client, header};
+use std::sync::Arc;
+use crate::upstream;
+
+const QUERY_PATH: &str = "/wechat-web/QueryController/Queryurl.html";
+const SELECT_PATH: &str = "/wechat-web/QueryController/select.html";
+
+#[derive(Debug, thiserror::Error)]
+pub enum EcardError {
+    #[error("Login expired.")]
+    SessionExpired,
+    /// The card system dropped its JSESSIONID, the CASTGC may still be good.
+    #[error("Card system session expired.")]
+    CardSessionExpired,
+    #[error("{0}")]
+    CardSystem(String),
+    #[error("Unexpected response from card system: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// Headers of a desktop browser, sent with every request to CAS and the card system.
+pub fn default_headers() -> header::HeaderMap {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3".parse().unwrap());
+    headers.insert(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8".parse().unwrap());
+    headers.insert(header::ACCEPT_ENCODING, "gzip, deflate, sdch".parse().unwrap());
+    headers.insert(header::ACCEPT_LANGUAGE, "zh-CN,zh;q=0.8".parse().unwrap());
+    headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
+    headers
+}
+
+/// Builds a client whose cookie jar holds the CASTGC for CAS.
+fn cas_client(castgc: &str) -> Result<(Client, Arc<reqwest::cookie::Jar>), EcardError> {
+    let cookie_store = Arc::new(reqwest::cookie::Jar::default());
+    let url_token = reqwest::Url::parse(&upstream::get().cas).unwrap();
+    cookie_store.add_cookie_str(("CASTGC=".to_owned() + castgc).as_str(), &url_token);
+    let client = Client::builder()
+        .cookie_provider(cookie_store.clone())
+        .default_headers(default_headers())
+        .connect_timeout(upstream::CONNECT_TIMEOUT)
+        .read_timeout(upstream::READ_TIMEOUT)
+        .build()?;
+    Ok((client, cookie_store))
+}
+
+/// The card system JSESSIONID in the URL CAS redirected to.
+fn jsession_of(url: &reqwest::Url) -> Result<String, EcardError> {
+    let re_jsession = regex::Regex::new(r#"jsessionid=(.*)"#).unwrap();
+    match re_jsession.captures(url.as_str()).and_then(|caps| caps.get(1)) {
+        Some(cap) => Ok(cap.as_str().to_string()),
+        None => Err(EcardError::SessionExpired),
+    }
+}
+
+/// Returns the card account number of the user the CASTGC belongs to.
+pub async fn get_account_no(castgc: &str) -> Result<String, EcardError> {
+    let regex = regex::Regex::new(r#"<input id="account" type="hidden" value="(.*)"/>"#).unwrap();
+    let (client, _) = cas_client(castgc)?;
+    let res = client.get(upstream::get().ecard_url(QUERY_PATH)).send().await?;
+    let resp = res.text().await?;
+    match regex.captures(resp.as_str()).and_then(|caps| caps.get(1)) {
+        Some(cap) => Ok(cap.as_str().to_string()),
+        None => Err(EcardError::SessionExpired),
+    }
+}
+
+/// Builds a client holding a card system JSESSIONID obtained through CAS with the given CASTGC.
+pub async fn get_client(castgc: &str) -> Result<Client, EcardError> {
+    let (client, cookie_store) = cas_client(castgc)?;
+    let res = client.get(upstream::get().ecard_url(QUERY_PATH)).send().await?;
+    let jsession = jsession_of(res.url())?;
+    let url = reqwest::Url::parse(&upstream::get().ecard).unwrap();
+    cookie_store.add_cookie_str(("JSESSIONID=".to_owned() + &jsession).as_str(), &url);
+    Ok(client)
+}
+
+/// Fetches one page of the transaction list, `date_status` being either `3`
+/// (last week) or the first day of a month as `YYYY-MM-01`.
+pub async fn select(client: &Client, account: &str, date_status: &str, page: u32) -> Result<serde_json::Value, EcardError> {
+    let res = client.get(upstream::get().ecard_url(SELECT_PATH))
+        .query(&[("account", account), ("curpage", &page.to_string()), ("typeStatus", "1"), ("dateStatus", date_status)])
+        .send().await?;
+    // Without a session the card system sends the browser back to CAS.
+    if !res.url().path().ends_with(SELECT_PATH) {
+        return Err(EcardError::CardSessionExpired);
+    }
+    let text = res.text().await?;
+    // The response is JSONP wrapped in `callback(...)`.
+    if text.len() < 10 {
+        return Err(EcardError::Parse(text));
+    }
+    let data: serde_json::Value = serde_json::from_str(&text[9..text.len()-1])
+        .map_err(|e| EcardError::Parse(e.to_string()))?;
+    if data["retcode"].as_str() != Some("0") {
+        let msg = data["errmsg"].as_str().unwrap_or("Card system error").to_string();
+        if msg.contains("会话") || msg.contains("重新登录") {
+            return Err(EcardError::CardSessionExpired);
+        }
+        return Err(EcardError::CardSystem(msg));
+    }
+    Ok(data)
+}
@@ -0,0 +1,57 @@
+//! Names of the Redis keys shared by the API and the worker.
+
+/// Jobs waiting for a worker, pushed by the API.
+pub const PENDING: &str = "jobs:pending";
+/// Failed jobs waiting for their next attempt, scored by the time they are due.
+pub const DELAYED: &str = "jobs:delayed";
+/// Jobs that failed for good.
+pub const DEAD: &str = "jobs:dead";
+/// Prefix of the per-worker lists holding the jobs being processed.
+pub const PROCESSING_PREFIX: &str = "jobs:processing:";
+/// Prefix of the per-worker heartbeat keys.
+pub const CONSUMER_PREFIX: &str = "jobs:consumer:";
+
+/// Holds the id of the queued job until the worker is done with it.
+pub fn request(account: &str, period: &str) -> String {
+    format!("request:{}:{}", account, period)
+}
+
+/// Seconds a request key is kept at most, so that a job lost along the way
+/// does not block its account and period for good.
+pub const REQUEST_TTL: u64 = 3600;
+
+/// Holds the serialized `JobResult` of the last job.
+pub fn result(account: &str, period: &str) -> String {
+    format!("result:{}:{}", account, period)
+}
+
+/// Hash holding the progress of a job.
+pub fn job_status(id: &str) -> String {
+    format!("job:{}", id)
+}
+
+/// Seconds the status of a job is kept after its last update.
+pub const STATUS_TTL: i64 = 86400;
+
+/// JSON list of the rules tagging merchants without a tag, in the tags
+/// database.
+pub const RULES: &str = "rules";
+
+/// JSON tree of the categories tags are taken from, in the tags database.
+pub const CATEGORIES: &str = "categories";
+
+/// Merchants waiting for review in the untagged database, scored by the time
+/// they were last seen.
+pub const UNTAGGED: &str = "untagged";
+
+/// Hash holding the name, hit count and first and last seen times of a
+/// merchant waiting for review.
+pub fn untagged(mercacc: &str) -> String {
+    format!("untagged:{}", mercacc)
+}
+
+/// Ids of the transactions counted in the hits of a merchant waiting for
+/// review, so that reports covering them again do not count them twice.
+pub fn untagged_seen(mercacc: &str) -> String {
+    format!("untagged:{}:seen", mercacc)
+}
@@ -0,0 +1,11 @@
+//! Data model, configuration loading, card system client, transaction store,
+//! merchant rules and Redis key naming shared by the API server and the worker.
+
+pub mod classifier;
+pub mod config;
+pub mod ecard;
+pub mod keys;
+pub mod model;
+pub mod store;
+pub mod upstream;
+pub mod vault;
@@ -0,0 +1,193 @@
+//! The tree merchant tags are taken from. A tag is the path of its category,
+//! codes joined by `/` from the top: `CAF`, `CAF/百景`, `LOG/laundry`.
+//!
+//! The tree is a JSON list kept under `keys::CATEGORIES` in the tags database
+//! and seeded from `categories.json`:
+//!
+//! ```json
+//! [
+//!     { "code": "CAF", "name": "食堂", "children": [{ "code": "百景", "name": "百景园" }] },
+//!     { "code": "OTH", "name": "其他" }
+//! ]
+//! ```
+
+use serde::{Serialize, Deserialize};
+
+/// Known merchant categories: cafeteria, groceries, logistics and other. Every
+/// tag starts with one of them.
+pub const TAGS: [&str; 4] = ["CAF", "GRO", "LOG", "OTH"];
+
+/// Separates the levels of a tag.
+pub const SEPARATOR: char = '/';
+
+#[derive(Debug, thiserror::Error)]
+pub enum CategoryError {
+    #[error("Unreadable categories: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Unknown top-level category {0}")]
+    UnknownTop(String),
+    #[error("Invalid category code {0:?}")]
+    InvalidCode(String),
+    #[error("Duplicate category {0}")]
+    Duplicate(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Category {
+    pub code: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Category>,
+}
+
+/// A checked category tree.
+#[derive(Clone, Debug)]
+pub struct Categories(Vec<Category>);
+
+/// The top-level category of a tag.
+pub fn top(tag: &str) -> &str {
+    tag.split(SEPARATOR).next().unwrap_or(tag)
+}
+
+/// Whether `tag` is `ancestor` or one of its subcategories.
+pub fn is_within(tag: &str, ancestor: &str) -> bool {
+    match tag.strip_prefix(ancestor) {
+        Some(rest) => rest.is_empty() || rest.starts_with(SEPARATOR),
+        None => false,
+    }
+}
+
+fn check(level: &[Category], parent: &str) -> Result<(), CategoryError> {
+    for (i, category) in level.iter().enumerate() {
+        let path = match parent {
+            "" => category.code.clone(),
+            _ => format!("{}{}{}", parent, SEPARATOR, category.code),
+        };
+        if category.code.trim().is_empty() || category.code.contains(SEPARATOR) {
+            return Err(CategoryError::InvalidCode(category.code.clone()));
+        }
+        if level[..i].iter().any(|c| c.code == category.code) {
+            return Err(CategoryError::Duplicate(path));
+        }
+        check(&category.children, &path)?;
+    }
+    Ok(())
+}
+
+impl Categories {
+    pub fn new(tree: Vec<Category>) -> Result<Categories, CategoryError> {
+        if let Some(category) = tree.iter().find(|c| !TAGS.contains(&c.code.as_str())) {
+            return Err(CategoryError::UnknownTop(category.code.clone()));
+        }
+        check(&tree, "")?;
+        Ok(Categories(tree))
+    }
+
+    pub fn from_json(json: &str) -> Result<Categories, CategoryError> {
+        Categories::new(serde_json::from_str(json)?)
+    }
+
+    pub fn tree(&self) -> &[Category] {
+        &self.0
+    }
+
+    /// The category a tag stands for. Top-level tags always stand for one,
+    /// even when the tree leaves it out.
+    fn find(&self, tag: &str) -> Option<&Category> {
+        let mut level = self.0.as_slice();
+        let mut found = None;
+        for code in tag.split(SEPARATOR) {
+            let category = level.iter().find(|c| c.code == code)?;
+            level = &category.children;
+            found = Some(category);
+        }
+        found
+    }
+
+    /// Whether a tag names a category of the tree.
+    pub fn contains(&self, tag: &str) -> bool {
+        TAGS.contains(&tag) || self.find(tag).is_some()
+    }
+
+    /// Display name of a tag, its last code when the tree has no such category.
+    pub fn name<'a>(&'a self, tag: &'a str) -> &'a str {
+        match self.find(tag) {
+            Some(category) => &category.name,
+            None => tag.rsplit(SEPARATOR).next().unwrap_or(tag),
+        }
+    }
+}
+
+impl Default for Categories {
+    /// The top-level categories alone.
+    fn default() -> Self {
+        let names = ["Cafeteria", "Groceries", "Logistics", "Other"];
+        Categories(TAGS.iter().zip(names).map(|(code, name)| Category {
+            code: code.to_string(),
+            name: name.to_string(),
+            children: Vec::new(),
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(code: &str, children: Vec<Category>) -> Category {
+        Category { code: code.to_string(), name: code.to_lowercase(), children }
+    }
+
+    #[test]
+    fn valid_tree() {
+        let categories = Categories::from_json(r#"[
+            { "code": "CAF", "name": "食堂", "children": [{ "code": "百景", "name": "百景园" }] },
+            { "code": "OTH", "name": "其他" }
+        ]"#).unwrap();
+        assert!(categories.contains("CAF/百景"));
+        assert!(categories.contains("GRO"));
+        assert!(!categories.contains("CAF/东园"));
+        assert!(!categories.contains("FOO/百景"));
+        assert_eq!(categories.name("CAF/百景"), "百景园");
+        assert_eq!(categories.name("CAF/东园"), "东园");
+        assert_eq!(categories.name("FOO/百景"), "百景");
+    }
+
+    #[test]
+    fn unknown_top() {
+        let error = Categories::new(vec![category("CAF", Vec::new()), category("FOO", Vec::new())]);
+        assert!(matches!(error, Err(CategoryError::UnknownTop(code)) if code == "FOO"));
+    }
+
+    #[test]
+    fn duplicate() {
+        let error = Categories::new(vec![category("CAF", Vec::new()), category("CAF", Vec::new())]);
+        assert!(matches!(error, Err(CategoryError::Duplicate(path)) if path == "CAF"));
+        let error = Categories::new(vec![
+            category("CAF", vec![category("百景", Vec::new()), category("东园", Vec::new()), category("百景", Vec::new())]),
+        ]);
+        assert!(matches!(error, Err(CategoryError::Duplicate(path)) if path == "CAF/百景"));
+        // The same code under different parents is fine.
+        assert!(Categories::new(vec![
+            category("CAF", vec![category("东区", Vec::new())]),
+            category("GRO", vec![category("东区", Vec::new())]),
+        ]).is_ok());
+    }
+
+    #[test]
+    fn invalid_code() {
+        for code in ["", " ", "百/景"] {
+            let error = Categories::new(vec![category("LOG", vec![category(code, Vec::new())])]);
+            assert!(matches!(error, Err(CategoryError::InvalidCode(c)) if c == code), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn ancestry() {
+        assert_eq!(top("CAF/百景"), "CAF");
+        assert!(is_within("CAF/百景", "CAF"));
+        assert!(is_within("CAF", "CAF"));
+        assert!(!is_within("CAFE", "CAF"));
+        assert!(!is_within("CAF", "CAF/百景"));
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Serialize, Deserialize};
+use crate::vault::{self, VaultError};
+
+/// A report generation request, as queued by the API.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub account: String,
+    pub period: String,
+    /// CASTGC of the account, sealed by the vault.
+    pub castgc: String,
+    pub attempts: u32,
+    pub enqueued_at: i64,
+    pub error: Option<String>,
+}
+
+impl Job {
+    pub fn new(account: String, period: String, castgc: &str) -> Job {
+        Job {
+            id: format!("{:032x}", rand::random::<u128>()),
+            castgc: vault::get().seal(castgc, &account),
+            account,
+            period,
+            attempts: 0,
+            enqueued_at: chrono::Utc::now().timestamp(),
+            error: None,
+        }
+    }
+
+    /// Whether `id` has the form of a job id.
+    pub fn valid_id(id: &str) -> bool {
+        id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// The CASTGC to fetch the transactions with.
+    pub fn castgc(&self) -> Result<String, VaultError> {
+        vault::get().open(&self.castgc, &self.account)
+    }
+}
+
+/// Machine-readable reason of a failed job.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    InvalidPeriod,
+    SessionExpired,
+    CardSystemError,
+    UpstreamUnavailable,
+    UpstreamParse,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn retryable(self) -> bool {
+        matches!(self, ErrorCode::CardSystemError | ErrorCode::UpstreamUnavailable | ErrorCode::Internal)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidPeriod => "InvalidPeriod",
+            ErrorCode::SessionExpired => "SessionExpired",
+            ErrorCode::CardSystemError => "CardSystemError",
+            ErrorCode::UpstreamUnavailable => "UpstreamUnavailable",
+            ErrorCode::UpstreamParse => "UpstreamParse",
+            ErrorCode::Internal => "Internal",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JobError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl JobError {
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        Self { code, message, retryable: code.retryable() }
+    }
+}
+
+/// Outcome of a job, stored under `keys::result` for the API.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum JobResult {
+    /// `report` is the `database/collection/id` path of the report.
+    Finished { report: String },
+    /// `castgc` is the sealed CASTGC of the job, telling which session an
+    /// expired CASTGC ends.
+    Failed {
+        error: JobError,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        castgc: Option<String>,
+    },
+}
@@ -0,0 +1,4 @@
+pub mod category;
+pub mod job;
+pub mod report;
+pub mod transaction;
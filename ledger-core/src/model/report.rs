@@ -0,0 +1,213 @@
+use serde::{Serialize, Deserialize};
+use super::category::{Categories, SEPARATOR};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Expense {
+    pub time: String,
+    pub location: String,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Trend {
+    pub count: i32,
+    pub expense: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Meal {
+    pub count: i32,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Trans{
+    pub location: String,
+    pub amount: f64,
+    pub count: i32,
+}
+
+/// Spending of a category, broken down by its subcategories.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Spending {
+    /// Path of the category, as in `CAF/百景`.
+    pub tag: String,
+    pub name: String,
+    pub count: i32,
+    pub amount: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Spending>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReportData {
+    pub date: String,
+    pub balance: f64,
+    pub total_expense: f64,
+    pub total_topup: f64,
+    pub total_count: i32,
+    pub top_expense: Expense,
+    pub top_count: Trans,
+    pub trend: Vec<Trend>,
+    pub cafeteria_count: i32,
+    pub cafeteria_amount: f64,
+    pub groceries_count: i32,
+    pub groceries_amount: f64,
+    pub logistics_count: i32,
+    pub logistics_amount: f64,
+    pub other_count: i32,
+    pub other_amount: f64,
+    pub breakfast: Meal,
+    pub lunch: Meal,
+    pub dinner: Meal,
+    pub midnight_snack: Meal,
+    /// Spending by category, each level of a tag counted in its own node.
+    /// Reports made before categories had subcategories have none.
+    #[serde(default)]
+    pub categories: Vec<Spending>,
+}
+
+impl Meal {
+    pub fn merge(self, other: Meal) -> Meal {
+        Meal {
+            count: self.count + other.count,
+            amount: self.amount + other.amount,
+        }
+    }
+}
+
+impl Spending {
+    /// Counts an expense under every level of `tag`.
+    pub fn record(nodes: &mut Vec<Spending>, categories: &Categories, tag: &str, amount: f64) {
+        let mut nodes = nodes;
+        let mut end = 0;
+        for code in tag.split(SEPARATOR) {
+            end += code.len();
+            let path = &tag[..end];
+            end += SEPARATOR.len_utf8();
+            let i = match nodes.iter().position(|n| n.tag == path) {
+                Some(i) => i,
+                None => {
+                    nodes.push(Spending {
+                        tag: path.to_string(),
+                        name: categories.name(path).to_string(),
+                        count: 0,
+                        amount: 0.0,
+                        children: Vec::new(),
+                    });
+                    nodes.len() - 1
+                }
+            };
+            let node = &mut nodes[i];
+            node.count += 1;
+            node.amount += amount;
+            nodes = &mut node.children;
+        }
+    }
+
+    /// Folds the spending of a later report into `nodes`.
+    pub fn merge(nodes: &mut Vec<Spending>, later: Vec<Spending>) {
+        for node in later {
+            match nodes.iter_mut().find(|n| n.tag == node.tag) {
+                Some(n) => {
+                    n.count += node.count;
+                    n.amount += node.amount;
+                    Spending::merge(&mut n.children, node.children);
+                },
+                None => nodes.push(node),
+            }
+        }
+    }
+}
+
+impl ReportData {
+    /// Folds a later report into this one. Per-merchant counts are not kept in
+    /// stored reports, so `top_count` is the busiest merchant of a single report.
+    pub fn merge(self, later: ReportData) -> ReportData {
+        let mut categories = self.categories;
+        Spending::merge(&mut categories, later.categories);
+        ReportData {
+            date: later.date,
+            balance: if later.balance >= 0.0 { later.balance } else { self.balance },
+            total_expense: self.total_expense + later.total_expense,
+            total_topup: self.total_topup + later.total_topup,
+            total_count: self.total_count + later.total_count,
+            top_expense: if later.top_expense.amount > self.top_expense.amount { later.top_expense } else { self.top_expense },
+            top_count: if later.top_count.count > self.top_count.count { later.top_count } else { self.top_count },
+            trend: self.trend,
+            cafeteria_count: self.cafeteria_count + later.cafeteria_count,
+            cafeteria_amount: self.cafeteria_amount + later.cafeteria_amount,
+            groceries_count: self.groceries_count + later.groceries_count,
+            groceries_amount: self.groceries_amount + later.groceries_amount,
+            logistics_count: self.logistics_count + later.logistics_count,
+            logistics_amount: self.logistics_amount + later.logistics_amount,
+            other_count: self.other_count + later.other_count,
+            other_amount: self.other_amount + later.other_amount,
+            breakfast: self.breakfast.merge(later.breakfast),
+            lunch: self.lunch.merge(later.lunch),
+            dinner: self.dinner.merge(later.dinner),
+            midnight_snack: self.midnight_snack.merge(later.midnight_snack),
+            categories,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn categories() -> Categories {
+        Categories::from_json(r#"[{ "code": "CAF", "name": "食堂", "children": [{ "code": "百景", "name": "百景园" }] }]"#).unwrap()
+    }
+
+    fn node<'a>(nodes: &'a [Spending], tag: &str) -> &'a Spending {
+        nodes.iter().find(|n| n.tag == tag).unwrap()
+    }
+
+    #[test]
+    fn record_counts_every_level() {
+        let categories = categories();
+        let mut nodes = Vec::new();
+        Spending::record(&mut nodes, &categories, "CAF/百景", 12.5);
+        Spending::record(&mut nodes, &categories, "CAF", 3.0);
+        Spending::record(&mut nodes, &categories, "OTH", 1.0);
+        assert_eq!(nodes.len(), 2);
+        let caf = node(&nodes, "CAF");
+        assert_eq!((caf.name.as_str(), caf.count, caf.amount), ("食堂", 2, 15.5));
+        let baijing = node(&caf.children, "CAF/百景");
+        assert_eq!((baijing.name.as_str(), baijing.count, baijing.amount), ("百景园", 1, 12.5));
+        assert!(node(&nodes, "OTH").children.is_empty());
+    }
+
+    #[test]
+    fn record_outside_the_tree() {
+        // A tag whose parent is missing from the tree still gets a node per
+        // level, named after its last code.
+        let mut nodes = Vec::new();
+        Spending::record(&mut nodes, &categories(), "LOG/laundry/东区", 4.0);
+        let log = node(&nodes, "LOG");
+        let laundry = node(&log.children, "LOG/laundry");
+        let east = node(&laundry.children, "LOG/laundry/东区");
+        assert_eq!((laundry.name.as_str(), east.name.as_str()), ("laundry", "东区"));
+        assert_eq!((log.count, laundry.count, east.count), (1, 1, 1));
+    }
+
+    #[test]
+    fn merge_folds_matching_nodes() {
+        let categories = categories();
+        let mut nodes = Vec::new();
+        Spending::record(&mut nodes, &categories, "CAF/百景", 10.0);
+        let mut later = Vec::new();
+        Spending::record(&mut later, &categories, "CAF/百景", 5.0);
+        Spending::record(&mut later, &categories, "CAF/东园", 2.0);
+        Spending::record(&mut later, &categories, "GRO", 7.0);
+        Spending::merge(&mut nodes, later);
+        assert_eq!(nodes.len(), 2);
+        let caf = node(&nodes, "CAF");
+        assert_eq!((caf.count, caf.amount), (3, 17.0));
+        assert_eq!(caf.children.len(), 2);
+        assert_eq!(node(&caf.children, "CAF/百景").amount, 15.0);
+        assert_eq!(node(&caf.children, "CAF/东园").count, 1);
+        assert_eq!(node(&nodes, "GRO").amount, 7.0);
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Serialize, Deserialize};
+
+/// A transaction as stored in the per-account `transactions` collection.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Transaction {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub occtime: i64,
+    pub mercacc: String,
+    pub mercname: String,
+    pub amount: f64,
+    pub balance: f64,
+    pub topup: bool,
+}
+
+fn field<'a>(item: &'a serde_json::Value, name: &str) -> Result<&'a str, String> {
+    item[name].as_str().ok_or_else(|| format!("Missing {} in transaction", name))
+}
+
+impl Transaction {
+    /// Parses an item of the `total` list returned by `select.html`.
+    pub fn parse(account: &str, item: &serde_json::Value) -> Result<Transaction, String> {
+        let occtime = field(item, "occtime")?.parse::<i64>().map_err(|e| e.to_string())?;
+        let mercacc = field(item, "mercacc")?.to_string();
+        let sign_tranamt = field(item, "sign_tranamt")?;
+        Ok(Transaction {
+            id: format!("{}-{}-{}-{}", account, occtime, mercacc, sign_tranamt),
+            occtime,
+            mercname: field(item, "mercname")?.to_string(),
+            amount: field(item, "tranamt")?.parse::<f64>().map_err(|e| e.to_string())? / 100.0,
+            balance: field(item, "cardbal")?.parse::<f64>().map_err(|e| e.to_string())? / 100.0,
+            topup: sign_tranamt.parse::<i64>().map_err(|e| e.to_string())? > 0,
+            mercacc,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+//! The per-account `transactions` collection, kept up to date from the card
+//! system by the worker when it generates reports and by the API when it
+//! lists transactions.
+
+use std::future::Future;
+use chrono::{Datelike as _, NaiveDate};
+use mongodb::{bson::{doc, Document}, Client as MongoClient, Collection};
+use crate::ecard::EcardError;
+use crate::model::transaction::Transaction;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Database error: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error(transparent)]
+    Ecard(#[from] EcardError),
+    #[error("Unexpected card system response: {0}")]
+    Parse(String),
+}
+
+/// The card system as seen by `sync`: the transaction list of one account.
+pub trait Source {
+    /// `ecard::select` for the account.
+    fn select(&self, date_status: &str, page: u32) -> impl Future<Output = Result<serde_json::Value, EcardError>> + Send;
+
+    /// Called once a page of `month` (`YYYYMM`) is stored, with the next page
+    /// announced by the card system, `0` being the last one.
+    fn fetched(&self, _month: &str, _page: u32, _nextpage: &str) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+fn collection(db: &MongoClient, account: &str) -> Collection<Transaction> {
+    db.database("transactions").collection(account)
+}
+
+/// `occtime` bounds covering the whole of the given days.
+pub fn bounds(from: NaiveDate, to: NaiveDate) -> (i64, i64) {
+    let lower = from.format("%Y%m%d000000").to_string().parse::<i64>().unwrap();
+    let upper = to.format("%Y%m%d235959").to_string().parse::<i64>().unwrap();
+    (lower, upper)
+}
+
+pub fn next_month(month_start: NaiveDate) -> NaiveDate {
+    (month_start + chrono::Duration::days(31)).with_day(1).unwrap()
+}
+
+/// Stores the transactions of the month starting at `month_start` that are not
+/// in the local store yet. The card system lists the newest transactions
+/// first, so paging stops at the first one older than the newest stored one.
+/// Months that are over are recorded in `sync_state` once fetched and never
+/// requested again.
+pub async fn sync_month<S: Source>(source: &S, account: &str, month_start: NaiveDate, db: &MongoClient) -> Result<(), StoreError> {
+    let state: Collection<Document> = db.database("sync_state").collection(account);
+    let key = month_start.format("%Y%m").to_string();
+    let finished = next_month(month_start) <= chrono::Local::now().date_naive();
+    if finished && state.find_one(doc! { "_id": &key }).await?.is_some() {
+        return Ok(());
+    }
+
+    let coll = collection(db, account);
+    let (lower, upper) = bounds(month_start, next_month(month_start) - chrono::Duration::days(1));
+    let last = coll.find_one(doc! { "occtime": { "$gte": lower, "$lte": upper } })
+        .sort(doc! { "occtime": -1 }).await?
+        .map(|t| t.occtime);
+
+    let date_status = month_start.format("%Y-%m-01").to_string();
+    let mut page = 1;
+    'pages: loop {
+        let data = source.select(&date_status, page).await?;
+        for item in data["total"].as_array().map(|t| t.as_slice()).unwrap_or_default() {
+            let t = Transaction::parse(account, item).map_err(StoreError::Parse)?;
+            if last.is_some_and(|last| t.occtime < last) {
+                break 'pages;
+            }
+            coll.replace_one(doc! { "_id": &t.id }, &t).upsert(true).await?;
+        }
+        let nextpage = data["nextpage"].as_str().unwrap_or("0").to_string();
+        source.fetched(&key, page, &nextpage).await;
+        page = match nextpage.parse::<u32>() {
+            Ok(next) if next != 0 => next,
+            _ => break,
+        };
+    };
+
+    if finished {
+        state.update_one(doc! { "_id": &key }, doc! { "$set": { "synced_at": chrono::Utc::now().to_rfc3339() } })
+            .upsert(true).await?;
+    }
+    Ok(())
+}
+
+/// Brings the local store up to date for every month touched by `from..=to`.
+pub async fn sync<S: Source>(source: &S, account: &str, from: NaiveDate, to: NaiveDate, db: &MongoClient) -> Result<(), StoreError> {
+    let mut month = from.with_day(1).unwrap();
+    while month <= to {
+        sync_month(source, account, month, db).await?;
+        month = next_month(month);
+    }
+    Ok(())
+}
+
+/// Stored transactions between the `occtime` bounds, newest first.
+pub async fn find(db: &MongoClient, account: &str, lower: i64, upper: i64) -> Result<Vec<Transaction>, StoreError> {
+    find_before(db, account, lower, upper, None, None).await
+}
+
+/// Like `find`, only returning the transactions listed after `before`, given
+/// as the `occtime` and id of a transaction, and at most `limit` of them.
+pub async fn find_before(db: &MongoClient, account: &str, lower: i64, upper: i64, before: Option<(i64, &str)>, limit: Option<i64>) -> Result<Vec<Transaction>, StoreError> {
+    let mut filter = doc! { "occtime": { "$gte": lower, "$lte": upper } };
+    if let Some((occtime, id)) = before {
+        filter.insert("$or", vec![
+            doc! { "occtime": { "$lt": occtime } },
+            doc! { "occtime": occtime, "_id": { "$lt": id } },
+        ]);
+    }
+    let coll = collection(db, account);
+    let mut find = coll
+        .find(filter)
+        .sort(doc! { "occtime": -1, "_id": -1 });
+    if let Some(limit) = limit {
+        find = find.limit(limit);
+    }
+    let mut cursor = find.await?;
+    let mut transactions = Vec::new();
+    while cursor.advance().await? {
+        transactions.push(cursor.deserialize_current()?);
+    }
+    Ok(transactions)
+}
+
+/// Card balance after the last stored transaction up to `upper`.
+pub async fn balance_at(db: &MongoClient, account: &str, upper: i64) -> Result<Option<f64>, StoreError> {
+    Ok(collection(db, account)
+        .find_one(doc! { "occtime": { "$lte": upper } })
+        .sort(doc! { "occtime": -1 }).await?
+        .map(|t| t.balance))
+}
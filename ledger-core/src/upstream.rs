@@ -0,0 +1,29 @@
+//! Base URLs of the HUST services the binaries talk to, set from the
+//! `[upstream]` section of the config at startup.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::config::config::Upstream;
+
+/// Longest wait for a connection to CAS or the card system.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest wait for the next bytes of a response, so that a stalled upstream
+/// fails the request instead of holding it forever.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+static UPSTREAM: OnceLock<Upstream> = OnceLock::new();
+
+/// Sets the upstream URLs. Only the first call has an effect.
+pub fn init(upstream: Upstream) {
+    for url in [&upstream.cas, &upstream.ecard] {
+        if let Err(e) = reqwest::Url::parse(url) {
+            panic!("Invalid upstream URL {}: {}", url, e);
+        }
+    }
+    let _ = UPSTREAM.set(upstream);
+}
+
+/// The upstream URLs, falling back to the defaults when `init` was not called.
+pub fn get() -> &'static Upstream {
+    UPSTREAM.get_or_init(Upstream::default)
+}
@@ -0,0 +1,146 @@
+//! Encryption of the credentials kept in Redis, CASTGCs and the logins
+//! waiting for their SMS code, with the keys of the `[credentials]` section
+//! of the config.
+//!
+//! A sealed credential reads `{key id}:{nonce and ciphertext, base64}`, so
+//! that keys can be rotated: add a new key, make it `current`, and drop the
+//! old one once the jobs and sessions it sealed have expired.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crate::config::config::Credentials;
+
+const NONCE_LEN: usize = 24;
+
+static VAULT: OnceLock<Vault> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("Invalid credential key {0}: {1}")]
+    InvalidKey(String, String),
+    #[error("Unknown credential key {0}")]
+    UnknownKey(String),
+    #[error("Malformed sealed credential")]
+    Malformed,
+    #[error("Credential could not be decrypted")]
+    Decrypt,
+}
+
+pub struct Vault {
+    current: String,
+    keys: HashMap<String, XChaCha20Poly1305>,
+}
+
+impl Vault {
+    pub fn new(settings: &Credentials) -> Result<Vault, VaultError> {
+        let mut keys = HashMap::new();
+        for (id, key) in &settings.keys {
+            if id.contains(':') {
+                return Err(VaultError::InvalidKey(id.clone(), "the id contains ':'".to_string()));
+            }
+            let key = STANDARD.decode(key).map_err(|e| VaultError::InvalidKey(id.clone(), e.to_string()))?;
+            let cipher = XChaCha20Poly1305::new_from_slice(&key)
+                .map_err(|_| VaultError::InvalidKey(id.clone(), format!("{} bytes instead of 32", key.len())))?;
+            keys.insert(id.clone(), cipher);
+        }
+        if !keys.contains_key(&settings.current) {
+            return Err(VaultError::UnknownKey(settings.current.clone()));
+        }
+        Ok(Vault { current: settings.current.clone(), keys })
+    }
+
+    /// Encrypts `secret` with the current key. `context` is authenticated
+    /// along with it and must be given again to open it, so that a sealed
+    /// credential cannot be moved to another account.
+    pub fn seal(&self, secret: &str, context: &str) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.keys[&self.current].encrypt(&nonce, Payload { msg: secret.as_bytes(), aad: context.as_bytes() }).unwrap());
+        format!("{}:{}", self.current, STANDARD.encode(sealed))
+    }
+
+    pub fn open(&self, sealed: &str, context: &str) -> Result<String, VaultError> {
+        let (id, data) = sealed.split_once(':').ok_or(VaultError::Malformed)?;
+        let cipher = self.keys.get(id).ok_or_else(|| VaultError::UnknownKey(id.to_string()))?;
+        let data = STANDARD.decode(data).map_err(|_| VaultError::Malformed)?;
+        if data.len() < NONCE_LEN {
+            return Err(VaultError::Malformed);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let secret = cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: context.as_bytes() })
+            .map_err(|_| VaultError::Decrypt)?;
+        String::from_utf8(secret).map_err(|_| VaultError::Decrypt)
+    }
+}
+
+/// Loads the keys. Only the first call has an effect.
+pub fn init(settings: &Credentials) {
+    if settings.keys.is_empty() {
+        panic!("No credential keys configured, see [credentials] in the README");
+    }
+    match Vault::new(settings) {
+        Ok(vault) => { let _ = VAULT.set(vault); },
+        Err(e) => panic!("Invalid [credentials] config: {}", e),
+    }
+}
+
+/// The vault set up by `init`.
+pub fn get() -> &'static Vault {
+    VAULT.get().expect("vault::init was not called")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vault holding `ids`, each key derived from its id alone so that
+    /// vaults of the same ids can open each other's credentials.
+    fn vault(current: &str, ids: &[&str]) -> Vault {
+        let keys = ids.iter()
+            .map(|id| (id.to_string(), STANDARD.encode([*id.as_bytes().last().unwrap(); 32])))
+            .collect();
+        Vault::new(&Credentials { current: current.to_string(), keys }).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let vault = vault("k1", &["k1"]);
+        let sealed = vault.seal("TGT-secret", "123456");
+        assert!(sealed.starts_with("k1:"));
+        assert!(!sealed.contains("TGT-secret"));
+        assert_eq!(vault.open(&sealed, "123456").unwrap(), "TGT-secret");
+    }
+
+    #[test]
+    fn opens_with_old_key_after_rotation() {
+        let sealed = vault("k1", &["k1"]).seal("TGT-secret", "123456");
+        let rotated = vault("k2", &["k1", "k2"]);
+        assert_eq!(rotated.open(&sealed, "123456").unwrap(), "TGT-secret");
+        assert!(rotated.seal("TGT-secret", "123456").starts_with("k2:"));
+    }
+
+    #[test]
+    fn unknown_key_once_removed() {
+        let sealed = vault("k1", &["k1", "k2"]).seal("TGT-secret", "123456");
+        let error = vault("k2", &["k2"]).open(&sealed, "123456").unwrap_err();
+        assert!(matches!(error, VaultError::UnknownKey(id) if id == "k1"));
+    }
+
+    #[test]
+    fn other_context_fails_to_decrypt() {
+        let vault = vault("k1", &["k1"]);
+        let sealed = vault.seal("TGT-secret", "123456");
+        assert!(matches!(vault.open(&sealed, "654321"), Err(VaultError::Decrypt)));
+    }
+
+    #[test]
+    fn malformed() {
+        let vault = vault("k1", &["k1"]);
+        for sealed in ["", "no separator", "k1:not base64!", "k1:c2hvcnQ="] {
+            assert!(matches!(vault.open(sealed, "123456"), Err(VaultError::Malformed)), "{:?}", sealed);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#!/bin/sh
+# Runs the login -> queue -> worker -> report flow against mock-upstream.
+# Redis and MongoDB are taken from config.toml in the repository root, the
+# API is expected to listen on $API. Run by hand only, cargo test does not
+# run it.
+set -e
+cd "$(dirname "$0")/.."
+
+API=${API:-http://127.0.0.1:8080}
+export HUST_CAS_URL=http://127.0.0.1:8081
+export HUST_ECARD_URL=http://127.0.0.1:8082
+
+cargo build --workspace
+target/debug/mock-upstream & MOCK=$!
+target/debug/worker & WORKER=$!
+target/debug/hust_ledger_backend & API_PID=$!
+trap 'kill $MOCK $WORKER $API_PID' EXIT
+sleep 3
+
+TOKEN=$(curl -sf -X POST "$API/login" -H 'Content-Type: application/json' \
+	-d '{"username":"U202312345","password":"password"}' | sed -n 's/.*"token":"\([^"]*\)".*/\1/p')
+[ -n "$TOKEN" ] || { echo "Login failed"; exit 1; }
+echo "Logged in: $TOKEN"
+
+for period in week month; do
+	for i in $(seq 1 30); do
+		STATUS=$(curl -s -o /tmp/report.json -w '%{http_code}' -H "Authorization: Bearer $TOKEN" "$API/report/$period")
+		[ "$STATUS" = 200 ] && break
+		[ "$STATUS" = 201 ] || { cat /tmp/report.json; exit 1; }
+		sleep 1
+	done
+	[ "$STATUS" = 200 ] || { echo "Timed out waiting for the $period report"; exit 1; }
+	echo "$period: $(cat /tmp/report.json)"
+done
+
+curl -sf -X POST -H "Authorization: Bearer $TOKEN" "$API/logout"
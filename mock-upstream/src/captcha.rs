@@ -0,0 +1,63 @@
+use image::{Frame, Rgba, RgbaImage};
+use image::codecs::gif::GifEncoder;
+use rand::Rng;
+
+const WIDTH: u32 = 90;
+const HEIGHT: u32 = 58;
+const FRAMES: usize = 4;
+const SCALE: u32 = 3;
+
+/// 5x7 glyphs of the digits, one row per byte with the leftmost pixel in bit 4.
+const GLYPHS: [[u8; 7]; 10] = [
+	[0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+	[0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+	[0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+	[0b11110, 0b00001, 0b00001, 0b01110, 0b00001, 0b00001, 0b11110],
+	[0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+	[0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+	[0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+	[0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+	[0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+	[0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+];
+
+pub fn random_code() -> String {
+	let mut rng = rand::thread_rng();
+	(0..4).map(|_| char::from(b'0' + rng.gen_range(0..10))).collect()
+}
+
+/// Renders `code` as an animated GIF like the CAS one: every frame shows the
+/// digits plus its own speckles, so only pixels set in most frames are text.
+pub fn render(code: &str) -> Result<Vec<u8>, image::ImageError> {
+	let mut rng = rand::thread_rng();
+	let mut frames = Vec::with_capacity(FRAMES);
+	for _ in 0..FRAMES {
+		let mut img = RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([255, 255, 255, 255]));
+		for (i, c) in code.chars().enumerate() {
+			let glyph = GLYPHS[c.to_digit(10).unwrap_or(0) as usize];
+			let (left, top) = (8 + i as u32 * 20, 18);
+			for (y, row) in glyph.iter().enumerate() {
+				for x in 0..5 {
+					if row & (0b10000 >> x) == 0 {
+						continue;
+					}
+					for dx in 0..SCALE {
+						for dy in 0..SCALE {
+							img.put_pixel(left + x * SCALE + dx, top + y as u32 * SCALE + dy, Rgba([40, 40, 120, 255]));
+						}
+					}
+				}
+			}
+		}
+		for _ in 0..80 {
+			img.put_pixel(rng.gen_range(0..WIDTH), rng.gen_range(0..HEIGHT), Rgba([120, 120, 120, 255]));
+		}
+		frames.push(Frame::new(img));
+	}
+	let mut buf = Vec::new();
+	{
+		let mut encoder = GifEncoder::new(&mut buf);
+		encoder.encode_frames(frames)?;
+	}
+	Ok(buf)
+}
@@ -0,0 +1,176 @@
+use actix_web::{web, HttpRequest, HttpResponse, cookie::Cookie};
+use base64::{engine::general_purpose, Engine};
+use rsa::Pkcs1v15Encrypt;
+use rsa::pkcs8::EncodePublicKey;
+use serde::Deserialize;
+use super::captcha;
+use super::state::{CasSession, State, token};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+	cfg.service(
+		web::scope("/cas")
+			.route("/login", web::get().to(login_page))
+			.route("/login", web::post().to(login))
+			.route("/rsa", web::post().to(rsa))
+			.route("/code", web::get().to(code))
+	);
+}
+
+#[derive(Deserialize)]
+pub struct Service {
+	service: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+	#[serde(default)]
+	ul: String,
+	#[serde(default)]
+	pl: String,
+	lt: String,
+	#[serde(default)]
+	code: String,
+	#[serde(rename = "phoneCode", default)]
+	phone_code: String,
+}
+
+fn login_form(lt: &str, error: Option<&str>) -> String {
+	format!(r#"<html><body>
+<form id="loginForm" method="post">
+<div class="error">{}</div>
+<input type="hidden" id="lt" name="lt" value="{}" />
+<input type="hidden" name="phoneCode" value="" />
+<input type="hidden" name="execution" value="e1s1" />
+<input type="hidden" name="_eventId" value="submit" />
+</form>
+</body></html>"#, error.unwrap_or(""), lt)
+}
+
+/// Second step for accounts with SMS verification.
+fn phone_code_form(lt: &str, error: Option<&str>) -> String {
+	format!(r#"<html><body>
+<form id="loginForm" method="post">
+<div class="error">{}</div>
+<p>请输入发送至绑定手机的短信验证码</p>
+<input type="hidden" id="lt" name="lt" value="{}" />
+<input type="text" id="phoneCode" name="phoneCode" value="" />
+<input type="hidden" name="execution" value="e1s2" />
+<input type="hidden" name="_eventId" value="submit" />
+</form>
+</body></html>"#, error.unwrap_or(""), lt)
+}
+
+/// Keeps the session of `jsession` waiting for the SMS code of `username`.
+fn challenge(state: &State, jsession: &str, username: String, error: Option<&str>) -> HttpResponse {
+	let lt = token("LT");
+	state.sessions.lock().unwrap().cas.insert(jsession.to_string(), CasSession { lt: lt.clone(), code: None, pending: Some(username) });
+	HttpResponse::Ok()
+		.content_type("text/html; charset=utf-8")
+		.body(phone_code_form(&lt, error))
+}
+
+fn redirect(service: &str, ticket: &str) -> HttpResponse {
+	let sep = if service.contains('?') { '&' } else { '?' };
+	HttpResponse::Found()
+		.insert_header(("Location", format!("{}{}ticket={}", service, sep, ticket)))
+		.finish()
+}
+
+/// Hands out a login page, or a service ticket when the CASTGC is still valid.
+async fn login_page(req: HttpRequest, query: web::Query<Service>, state: web::Data<State>) -> HttpResponse {
+	let mut sessions = state.sessions.lock().unwrap();
+	if let (Some(service), Some(tgt)) = (&query.service, req.cookie("CASTGC")) {
+		if let Some(username) = sessions.tgts.get(tgt.value()).cloned() {
+			let ticket = token("ST");
+			sessions.tickets.insert(ticket.clone(), username);
+			return redirect(service, &ticket);
+		}
+	}
+	let jsession = token("CAS");
+	let lt = token("LT");
+	sessions.cas.insert(jsession.clone(), CasSession { lt: lt.clone(), code: None, pending: None });
+	HttpResponse::Ok()
+		.cookie(Cookie::build("JSESSIONID", jsession).path("/cas").finish())
+		.content_type("text/html; charset=utf-8")
+		.body(login_form(&lt, None))
+}
+
+async fn rsa(state: web::Data<State>) -> HttpResponse {
+	let der = state.key.to_public_key().to_public_key_der().unwrap();
+	HttpResponse::Ok().json(serde_json::json!({ "publicKey": general_purpose::STANDARD.encode(der.as_bytes()) }))
+}
+
+async fn code(req: HttpRequest, state: web::Data<State>) -> HttpResponse {
+	let code = captcha::random_code();
+	match req.cookie("JSESSIONID").and_then(|c| state.sessions.lock().unwrap().cas.get_mut(c.value()).map(|s| s.code = Some(code.clone()))) {
+		Some(()) => {},
+		None => return HttpResponse::Forbidden().finish(),
+	}
+	match captcha::render(&code) {
+		Ok(gif) => HttpResponse::Ok().content_type("image/gif").body(gif),
+		Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+	}
+}
+
+fn decrypt(state: &State, field: &str) -> Option<String> {
+	let bytes = general_purpose::STANDARD.decode(field).ok()?;
+	String::from_utf8(state.key.decrypt(Pkcs1v15Encrypt, &bytes).ok()?).ok()
+}
+
+/// Checks the submitted credentials, then the SMS code for the accounts that
+/// have one, issuing a CASTGC on success. Failures render the login page
+/// again, as CAS does.
+async fn login(req: HttpRequest, query: web::Query<Service>, form: web::Form<LoginForm>, state: web::Data<State>) -> HttpResponse {
+	let failed = |msg: &str| HttpResponse::Ok()
+		.content_type("text/html; charset=utf-8")
+		.body(login_form(&token("LT"), Some(msg)));
+
+	let jsession = match req.cookie("JSESSIONID") {
+		Some(c) => c.value().to_string(),
+		None => return failed("Session expired"),
+	};
+	let session = match state.sessions.lock().unwrap().cas.remove(&jsession) {
+		Some(session) => session,
+		None => return failed("Session expired"),
+	};
+	if session.lt != form.lt {
+		return failed("Invalid login ticket");
+	}
+	if let Some(username) = session.pending {
+		let expected = state.user(&username).and_then(|u| u.phone_code.clone());
+		if expected.as_deref() != Some(form.phone_code.as_str()) {
+			return challenge(&state, &jsession, username, Some("短信验证码错误"));
+		}
+		return logged_in(&state, &query, username);
+	}
+	if state.strict_captcha && session.code.as_deref().map(|c| c.eq_ignore_ascii_case(&form.code)) != Some(true) {
+		return failed("验证码错误");
+	}
+	let (username, password) = match (decrypt(&state, &form.ul), decrypt(&state, &form.pl)) {
+		(Some(u), Some(p)) => (u, p),
+		_ => return failed("Malformed credentials"),
+	};
+	match state.user(&username) {
+		Some(user) if user.password == password => {
+			if user.phone_code.is_some() {
+				return challenge(&state, &jsession, username, None);
+			}
+		},
+		_ => return failed("用户名或密码错误"),
+	}
+	logged_in(&state, &query, username)
+}
+
+fn logged_in(state: &State, query: &Service, username: String) -> HttpResponse {
+	let tgt = token("TGT");
+	let ticket = token("ST");
+	let mut sessions = state.sessions.lock().unwrap();
+	sessions.tgts.insert(tgt.clone(), username.clone());
+	sessions.tickets.insert(ticket.clone(), username);
+	let mut res = match &query.service {
+		Some(service) => redirect(service, &ticket),
+		None => HttpResponse::Ok().finish(),
+	};
+	res.add_cookie(&Cookie::build("CASTGC", tgt).path("/cas").finish()).unwrap();
+	res
+}
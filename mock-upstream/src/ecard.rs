@@ -0,0 +1,111 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Datelike as _, Local, NaiveDate};
+use serde::Deserialize;
+use super::fixtures;
+use super::state::{State, token};
+
+const PAGE_SIZE: usize = 10;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+	cfg.service(
+		web::scope("/wechat-web/QueryController")
+			.route("/select.html", web::get().to(select))
+			.route("/{page}", web::get().to(query_url))
+	);
+}
+
+#[derive(Deserialize)]
+pub struct Ticket {
+	ticket: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SelectQuery {
+	account: String,
+	curpage: usize,
+	#[serde(rename = "dateStatus")]
+	date_status: String,
+}
+
+/// `Queryurl.html` sends users without a session through CAS and, once back
+/// with a ticket, to `Queryurl.html;jsessionid=...`, which shows the account.
+async fn query_url(req: HttpRequest, page: web::Path<(String,)>, query: web::Query<Ticket>, state: web::Data<State>) -> HttpResponse {
+	let page = &page.0;
+	if let Some(jsession) = page.strip_prefix("Queryurl.html;jsessionid=") {
+		return match state.sessions.lock().unwrap().ecard.get(jsession) {
+			Some(account) => HttpResponse::Ok()
+				.content_type("text/html; charset=utf-8")
+				.body(format!(r#"<html><body><input id="account" type="hidden" value="{}"/></body></html>"#, account)),
+			None => HttpResponse::Found().insert_header(("Location", "Queryurl.html")).finish(),
+		};
+	}
+	if page != "Queryurl.html" {
+		return HttpResponse::NotFound().finish();
+	}
+
+	let mut sessions = state.sessions.lock().unwrap();
+	let username = query.ticket.as_ref().and_then(|t| sessions.tickets.remove(t));
+	match username.and_then(|u| state.user(&u)) {
+		Some(user) => {
+			let jsession = token("EC");
+			sessions.ecard.insert(jsession.clone(), user.account.clone());
+			HttpResponse::Found()
+				.insert_header(("Location", format!("Queryurl.html;jsessionid={}", jsession)))
+				.finish()
+		},
+		None => {
+			let info = req.connection_info();
+			let service = format!("{}://{}{}", info.scheme(), info.host(), req.path());
+			HttpResponse::Found()
+				.insert_header(("Location", format!("{}/cas/login?service={}", state.cas_url, encode_component(&service))))
+				.finish()
+		},
+	}
+}
+
+fn encode_component(s: &str) -> String {
+	s.bytes().map(|b| match b {
+		b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+		_ => format!("%{:02X}", b),
+	}).collect()
+}
+
+fn jsonp(data: serde_json::Value) -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("text/javascript; charset=utf-8")
+		.body(format!("callback({})", data))
+}
+
+/// One page of the transaction list of a month, or of the last week when
+/// `dateStatus` is `3`, wrapped in JSONP like the real endpoint.
+async fn select(req: HttpRequest, query: web::Query<SelectQuery>, state: web::Data<State>) -> HttpResponse {
+	let account = req.cookie("JSESSIONID").and_then(|c| state.sessions.lock().unwrap().ecard.get(c.value()).cloned());
+	if account.as_deref() != Some(query.account.as_str()) {
+		return jsonp(serde_json::json!({ "retcode": "1", "errmsg": "会话已失效，请重新登录" }));
+	}
+	let today = Local::now().date_naive();
+	let trades = if query.date_status == "3" {
+		let from = today - chrono::Duration::days(6);
+		let mut trades = fixtures::month_trades(&query.account, today.with_day0(0).unwrap());
+		if from.month() != today.month() {
+			trades.extend(fixtures::month_trades(&query.account, from.with_day0(0).unwrap()));
+		}
+		trades.retain(|t| t.occtime.date() >= from);
+		trades
+	} else {
+		match NaiveDate::parse_from_str(&query.date_status, "%Y-%m-%d") {
+			Ok(month) => fixtures::month_trades(&query.account, month.with_day0(0).unwrap()),
+			Err(_) => return jsonp(serde_json::json!({ "retcode": "2", "errmsg": "参数错误" })),
+		}
+	};
+
+	let page = query.curpage.max(1);
+	let items: Vec<_> = trades.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE).map(|t| t.to_json()).collect();
+	let nextpage = if trades.len() > page * PAGE_SIZE { page + 1 } else { 0 };
+	jsonp(serde_json::json!({
+		"retcode": "0",
+		"errmsg": "",
+		"total": items,
+		"nextpage": nextpage.to_string(),
+	}))
+}
@@ -0,0 +1,140 @@
+use chrono::{Datelike as _, Local, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use std::env;
+
+/// Merchants of the live card system, shared with the worker's tag seed.
+const TAGS: &str = include_str!("../../tags.json");
+
+#[derive(Deserialize, Clone)]
+pub struct User {
+	pub username: String,
+	pub password: String,
+	pub account: String,
+	/// SMS code asked after the password, for accounts with SMS verification.
+	#[serde(default)]
+	pub phone_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Merchant {
+	mercacc: i32,
+	mercname: String,
+	tag: String,
+}
+
+/// A transaction as listed by `select.html`, amounts in cents.
+pub struct Trade {
+	pub occtime: NaiveDateTime,
+	pub mercacc: String,
+	pub mercname: String,
+	pub tranamt: i64,
+	pub topup: bool,
+	pub cardbal: i64,
+}
+
+impl Trade {
+	pub fn to_json(&self) -> serde_json::Value {
+		let sign = if self.topup { self.tranamt } else { -self.tranamt };
+		serde_json::json!({
+			"occtime": self.occtime.format("%Y%m%d%H%M%S").to_string(),
+			"mercacc": self.mercacc,
+			"mercname": self.mercname,
+			"tranamt": self.tranamt.to_string(),
+			"sign_tranamt": sign.to_string(),
+			"cardbal": self.cardbal.to_string(),
+		})
+	}
+}
+
+/// Users accepted by CAS, read from the JSON file at `MOCK_USERS` if set.
+pub fn load_users() -> Result<Vec<User>, Box<dyn std::error::Error>> {
+	match env::var("MOCK_USERS") {
+		Ok(path) => Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+		Err(_) => Ok(vec![User {
+			username: "U202312345".to_string(),
+			password: "password".to_string(),
+			account: "123456".to_string(),
+			phone_code: None,
+		}]),
+	}
+}
+
+/// Deterministic xorshift, so that the same account and month always list
+/// the same transactions.
+struct Rng(u64);
+
+impl Rng {
+	fn new(account: &str, month: NaiveDate) -> Self {
+		let mut seed = month.year() as u64 * 12 + month.month() as u64;
+		for b in account.bytes() {
+			seed = seed.wrapping_mul(31).wrapping_add(b as u64);
+		}
+		Rng(seed | 1)
+	}
+
+	fn next(&mut self, bound: u64) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0 % bound
+	}
+}
+
+/// Spending of a day: meals at the cafeterias, the odd grocery or logistics
+/// purchase and once in a while a merchant missing from `tags.json`.
+const SLOTS: [(&str, u64, u32, u32, i64, i64); 7] = [
+	// tag, chance in %, first hour, last hour, min and max amount in cents
+	("CAF", 60, 7, 8, 300, 800),
+	("CAF", 90, 11, 12, 800, 2000),
+	("CAF", 80, 17, 18, 800, 2000),
+	("CAF", 15, 22, 23, 500, 1500),
+	("GRO", 25, 9, 21, 200, 3000),
+	("LOG", 5, 9, 17, 100, 1000),
+	("NEW", 3, 9, 21, 100, 2000),
+];
+
+/// Transactions of the month starting at `month_start` up to now, newest first.
+pub fn month_trades(account: &str, month_start: NaiveDate) -> Vec<Trade> {
+	let merchants: Vec<Merchant> = serde_json::from_str(TAGS).unwrap();
+	let now = Local::now().naive_local();
+	let mut rng = Rng::new(account, month_start);
+	let mut balance = 5000 + rng.next(20000) as i64;
+	let mut trades = Vec::new();
+	let mut day = month_start;
+	while day.month() == month_start.month() && day <= now.date() {
+		for (tag, chance, first, last, min, max) in SLOTS {
+			if rng.next(100) >= chance {
+				continue;
+			}
+			let hour = first + rng.next((last - first + 1) as u64) as u32;
+			let occtime = day.and_hms_opt(hour, rng.next(60) as u32, rng.next(60) as u32).unwrap();
+			if occtime > now {
+				continue;
+			}
+			let (mercacc, mercname) = if tag == "NEW" {
+				("1999999".to_string(), "东校区打印店".to_string())
+			} else {
+				let candidates: Vec<&Merchant> = merchants.iter().filter(|m| m.tag == tag).collect();
+				let m = candidates[rng.next(candidates.len() as u64) as usize];
+				(m.mercacc.to_string(), m.mercname.clone())
+			};
+			let tranamt = min + rng.next((max - min) as u64) as i64;
+			if balance < tranamt {
+				balance += 10000;
+				trades.push(Trade {
+					occtime: occtime - chrono::Duration::minutes(1),
+					mercacc: "1000001".to_string(),
+					mercname: "微信充值".to_string(),
+					tranamt: 10000,
+					topup: true,
+					cardbal: balance,
+				});
+			}
+			balance -= tranamt;
+			trades.push(Trade { occtime, mercacc, mercname, tranamt, topup: false, cardbal: balance });
+		}
+		day = day.succ_opt().unwrap();
+	}
+	trades.sort_by_key(|t| std::cmp::Reverse(t.occtime));
+	trades
+}
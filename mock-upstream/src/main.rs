@@ -0,0 +1,45 @@
+//! Local stand-in for `pass.hust.edu.cn` and `ecard.m.hust.edu.cn`, serving
+//! just enough of CAS and the card system for the API and the worker to run
+//! offline. Point them at it with `HUST_CAS_URL` and `HUST_ECARD_URL`.
+
+pub mod captcha;
+pub mod cas;
+pub mod ecard;
+pub mod fixtures;
+pub mod state;
+
+use actix_web::{web, App, HttpServer, middleware::Logger};
+use env_logger::Env;
+use std::env;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+	env_logger::init_from_env(Env::default().default_filter_or("info"));
+
+	let cas_addr = env::var("MOCK_CAS_ADDR").unwrap_or("127.0.0.1:8081".to_string());
+	let ecard_addr = env::var("MOCK_ECARD_ADDR").unwrap_or("127.0.0.1:8082".to_string());
+	let cas_url = env::var("MOCK_CAS_URL").unwrap_or(format!("http://{}", cas_addr));
+	let users = match fixtures::load_users() {
+		Ok(users) => users,
+		Err(e) => panic!("Failed to load users: {}", e),
+	};
+	let state = web::Data::new(state::State::new(users, cas_url));
+
+	let cas_state = state.clone();
+	let cas = HttpServer::new(move || {
+		App::new()
+			.app_data(cas_state.clone())
+			.wrap(Logger::new("cas %r %s"))
+			.configure(cas::config)
+	}).bind(&cas_addr)?.run();
+	let ecard = HttpServer::new(move || {
+		App::new()
+			.app_data(state.clone())
+			.wrap(Logger::new("ecard %r %s"))
+			.configure(ecard::config)
+	}).bind(&ecard_addr)?.run();
+
+	println!("CAS listening on {}, card system on {}", cas_addr, ecard_addr);
+	actix_web::rt::spawn(cas);
+	ecard.await
+}
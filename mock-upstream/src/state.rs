@@ -0,0 +1,55 @@
+use rsa::RsaPrivateKey;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use super::fixtures::User;
+
+/// A CAS login page handed out, identified by its JSESSIONID.
+pub struct CasSession {
+	pub lt: String,
+	pub code: Option<String>,
+	/// User whose password was accepted, waiting for the SMS code.
+	pub pending: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Sessions {
+	pub cas: HashMap<String, CasSession>,
+	/// CASTGC to username.
+	pub tgts: HashMap<String, String>,
+	/// Service tickets to username, valid once.
+	pub tickets: HashMap<String, String>,
+	/// Card system JSESSIONID to account number.
+	pub ecard: HashMap<String, String>,
+}
+
+pub struct State {
+	pub users: Vec<User>,
+	pub key: RsaPrivateKey,
+	/// Where the card system sends users without a session to log in.
+	pub cas_url: String,
+	/// Whether the captcha has to be entered correctly. Off by default so that
+	/// OCR misreads do not get in the way of end-to-end runs.
+	pub strict_captcha: bool,
+	pub sessions: Mutex<Sessions>,
+}
+
+impl State {
+	pub fn new(users: Vec<User>, cas_url: String) -> Self {
+		Self {
+			users,
+			key: RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("Failed to generate RSA key"),
+			cas_url,
+			strict_captcha: env::var("MOCK_STRICT_CAPTCHA").is_ok_and(|v| v == "1"),
+			sessions: Mutex::new(Sessions::default()),
+		}
+	}
+
+	pub fn user(&self, username: &str) -> Option<&User> {
+		self.users.iter().find(|u| u.username == username)
+	}
+}
+
+pub fn token(prefix: &str) -> String {
+	format!("{}-{:024x}", prefix, rand::random::<u128>() >> 32)
+}
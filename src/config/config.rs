@@ -1,6 +1,5 @@
-use config_file::FromConfigFile;
 use serde::Deserialize;
-use std::env;
+use ledger_core::config::config::{Credentials, Database, Redis, Server, TagsDB, UntaggedDB, Upstream};
 
 #[derive(Deserialize, Clone)]
 pub struct Config {
@@ -8,74 +7,75 @@ pub struct Config {
     pub redis: Redis,
     pub server: Server,
     pub tags_db: TagsDB,
+    pub untagged_db: UntaggedDB,
+    #[serde(default)]
+    pub upstream: Upstream,
+    #[serde(default)]
+    pub login: Login,
+    #[serde(default)]
+    pub session: Session,
+    #[serde(default)]
+    pub credentials: Credentials,
+    #[serde(default)]
+    pub admin: Admin,
 }
 
 #[derive(Deserialize, Clone)]
-pub struct Database {
-    pub url: String,
+#[serde(default)]
+pub struct Login {
+    /// Captchas read, across login pages, before a login is given up.
+    pub captcha_attempts: u32,
+    /// Lowest recognizer confidence, from 0 to 100, for a reading to be submitted.
+    pub min_confidence: i32,
+    pub recognizer: RecognizerKind,
 }
 
-#[derive(Deserialize, Clone)]
-pub struct Redis {
-    pub url: String,
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RecognizerKind {
+    /// libtesseract, needs the `tesseract` feature.
+    Tesseract,
+    /// Experimental template matcher of `captcha-ocr`. Its templates come
+    /// from captchas rendered by `mock-upstream`, not from CAS ones.
+    Native,
 }
 
-#[derive(Deserialize, Clone)]
-pub struct Server {
-    pub host: String,
-    pub port: u16,
+impl Default for Login {
+    fn default() -> Self {
+        Self {
+            captcha_attempts: 5,
+            min_confidence: 0,
+            recognizer: RecognizerKind::Tesseract,
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
-pub struct TagsDB {
-    pub url: String,
+#[serde(default)]
+pub struct Session {
+    /// Key the session tokens are signed with. A random one is used when
+    /// unset, so tokens do not survive a restart nor work across instances.
+    pub secret: Option<String>,
+    /// Seconds a session lasts after login.
+    pub ttl: u64,
 }
 
-pub fn init_config_from_file(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    match Config::from_config_file(path){
-		Ok(config) => Ok(config),
-		Err(e) => Err(Box::new(e)),
-	}
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            ttl: 7200,
+        }
+    }
 }
 
-pub fn init_config_from_str(text: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    match toml::from_str(text){
-        Ok(config) => Ok(config),
-        Err(e) => Err(Box::new(e)),
-    }
+#[derive(Deserialize, Clone, Default)]
+pub struct Admin {
+    /// Token the ops team manages tags with, sent as `X-Admin-Token`. Tag
+    /// management is disabled when unset.
+    pub token: Option<String>,
 }
 
 pub async fn init_config() -> Config {
-    match env::var_os("APP_NAME"){
-        Some(val) => {
-            match reqwest::get(format!("http://cc-server.config-center/{}/config.toml/raw", val.into_string().unwrap())).await{
-                Ok(res) => {
-                    match res.text().await{
-                        Ok(text) => {
-                            match init_config_from_str(text.as_str()) {
-                                Ok(config) => config,
-                                Err(e) => {
-                                    panic!("Failed to load config: {}", e);
-                                },
-                            }
-                        },
-                        Err(e) => {
-                            panic!("Failed to load config: {}", e);
-                        }
-                    }
-                },
-                Err(e) => {
-                    panic!("Failed to load config from config-center: {}", e);
-                }
-            }
-        },
-        None => {
-            match init_config_from_file("config.toml") {
-                Ok(config) => config,
-                Err(e) => {
-                    panic!("Failed to load config from file: {}", e);
-                },
-            }
-        }
-    }
-}
\ No newline at end of file
+    ledger_core::config::config::init_config().await
+}
@@ -0,0 +1,37 @@
+use serde::Serialize;
+use super::super::model::job::{JobStatus, get_status};
+use super::super::model::session::Sessions;
+use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use redis::Client as RedisClient;
+
+#[derive(Serialize)]
+pub struct Job{
+	status: i32,
+	msg: String,
+	data: Option<JobStatus>,
+}
+
+pub async fn get_job(req: HttpRequest, path: web::Path<(String,)>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let session = match sessions.authenticate(&req, &redis_client)? {
+		Some(session) => session,
+		None => return Ok(HttpResponse::Unauthorized().json(Job{
+			status: 401,
+			msg: "Invalid or expired session".to_string(),
+			data: None,
+		})),
+	};
+
+	let mut con = redis_client.get_connection()?;
+	match get_status(&mut con, &path.0)? {
+		Some(job) if job.account == session.account => Ok(HttpResponse::Ok().json(Job{
+			status: 200,
+			msg: "Success".to_string(),
+			data: Some(job),
+		})),
+		_ => Ok(HttpResponse::NotFound().json(Job{
+			status: 404,
+			msg: "Job not found".to_string(),
+			data: None,
+		})),
+	}
+}
@@ -1,2 +1,4 @@
+pub mod jobs;
 pub mod report;
-pub mod tags;
\ No newline at end of file
+pub mod tags;
+pub mod transactions;
\ No newline at end of file
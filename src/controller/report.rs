@@ -1,7 +1,9 @@
-use serde::Serialize;
-use super::super::model::report::{Status, ReportData, get_report};
-use super::super::utils::hust_login::get_account_no;
-use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use serde::{Serialize, Deserialize};
+use super::super::model::report::{Status, get_report, range_period};
+use super::super::model::session::Sessions;
+use ledger_core::model::job::{ErrorCode, JobError};
+use ledger_core::model::report::ReportData;
+use actix_web::{web, HttpResponse, Responder, HttpRequest, http::StatusCode};
 use mongodb::Client as MongoClient;
 use redis::Client as RedisClient;
 
@@ -10,48 +12,92 @@ pub struct Report{
 	status: i32,
 	msg: String,
 	data: Option<ReportData>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	job: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<JobError>,
 }
 
-pub async fn report(req: HttpRequest, path: web::Path<(String,)>, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
-	let castgc = match req.headers().get("CASTGC") {
-		Some(header_value) => header_value.to_str().unwrap_or("").to_string(),
+#[derive(Deserialize)]
+pub struct RangeQuery{
+	from: String,
+	to: String,
+}
+
+pub async fn report(req: HttpRequest, path: web::Path<(String,)>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	generate(req, &path.0, sessions, redis_client, mongo_client).await
+}
+
+pub async fn range_report(req: HttpRequest, query: web::Query<RangeQuery>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let period = match range_period(&query.from, &query.to) {
+		Some(p) => p,
 		None => return Ok(HttpResponse::BadRequest().json(Report{
 			status: 400,
-			msg: "Missing CASTGC header".to_string(),
+			msg: "Invalid date range".to_string(),
 			data: None,
+			job: None,
+			error: None,
 		})),
 	};
+	generate(req, &period, sessions, redis_client, mongo_client).await
+}
 
-	let period = path.0.clone();
+fn status_code(code: ErrorCode) -> StatusCode {
+	match code {
+		ErrorCode::InvalidPeriod => StatusCode::BAD_REQUEST,
+		ErrorCode::SessionExpired => StatusCode::FORBIDDEN,
+		ErrorCode::CardSystemError | ErrorCode::UpstreamParse => StatusCode::BAD_GATEWAY,
+		ErrorCode::UpstreamUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+		ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+}
 
-	let account_no = match get_account_no(&castgc).await{
-		Ok(t) => t,
-		Err(e) => {return Ok(HttpResponse::Forbidden().json(Report{
-			status: 403,
-			msg: e.to_string(),
+async fn generate(req: HttpRequest, period: &str, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+	let session = match sessions.authenticate(&req, &redis_client)? {
+		Some(session) => session,
+		None => return Ok(HttpResponse::Unauthorized().json(Report{
+			status: 401,
+			msg: "Invalid or expired session".to_string(),
 			data: None,
-		}));},
+			job: None,
+			error: None,
+		})),
 	};
-	match get_report(account_no, &period, &castgc, redis_client, mongo_client).await?{
-		Status::Created => Ok(HttpResponse::Created().json(Report{
+
+	match get_report(session.account, period, &session.castgc, redis_client.clone(), mongo_client).await?{
+		Status::Created(job) => Ok(HttpResponse::Created().json(Report{
 			status: 201,
 			msg: "Report generation queued".to_string(),
 			data: None,
+			job: Some(job),
+			error: None,
 		})),
-		Status::Processing => Ok(HttpResponse::Created().json(Report{
+		Status::Processing(job) => Ok(HttpResponse::Created().json(Report{
 			status: 201,
 			msg: "Report is being generated".to_string(),
 			data: None,
+			job: Some(job),
+			error: None,
 		})),
 		Status::Finished(data) => Ok(HttpResponse::Ok().json(Report{
 			status: 200,
 			msg: "Success".to_string(),
 			data: Some(data),
+			job: None,
+			error: None,
 		})),
-		Status::Error(e) => Ok(HttpResponse::InternalServerError().json(Report{
-			status: 500,
-			msg: e.to_string(),
-			data: None,
-		})),
+		Status::Failed(e) => {
+			if e.code == ErrorCode::SessionExpired {
+				sessions.expire(&req, &redis_client)?;
+			}
+			let code = status_code(e.code);
+			Ok(HttpResponse::build(code).json(Report{
+				status: code.as_u16() as i32,
+				msg: e.message.clone(),
+				data: None,
+				job: None,
+				error: Some(e),
+			}))
+		},
 	}
-}
\ No newline at end of file
+}
@@ -1,9 +1,64 @@
-use super::super::model::tags;
-use actix_web::{web, HttpResponse, Responder};
+use serde::{Serialize, Deserialize};
+use super::super::model::tags::{self, Tag, Untagged, UntaggedClient};
+use ledger_core::model::category::{Categories, Category};
+use super::super::config::config::Admin;
+use actix_web::{web, HttpResponse, HttpRequest, Responder};
 use redis::Client as RedisClient;
+use sha2::{Digest, Sha256};
 
 type TagsClient = Option<RedisClient>;
 
+#[derive(Serialize)]
+pub struct TagResponse{
+	status: i32,
+	msg: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct TagBody{
+	tag: String,
+}
+
+#[derive(Serialize)]
+pub struct UntaggedResponse{
+	status: i32,
+	msg: String,
+	data: Vec<Untagged>,
+}
+
+#[derive(Deserialize)]
+pub struct UntaggedQuery{
+	limit: Option<usize>,
+}
+
+const DEFAULT_UNTAGGED_LIMIT: usize = 100;
+
+fn respond(status: i32, msg: &str) -> HttpResponse {
+	let body = TagResponse{
+		status,
+		msg: msg.to_string(),
+		count: None,
+	};
+	match status {
+		400 => HttpResponse::BadRequest().json(body),
+		401 => HttpResponse::Unauthorized().json(body),
+		404 => HttpResponse::NotFound().json(body),
+		_ => HttpResponse::Ok().json(body),
+	}
+}
+
+/// Whether the request carries the admin token. Tag management is disabled
+/// when no token is configured.
+fn is_admin(req: &HttpRequest, admin: &Admin) -> bool {
+	let (expected, given) = match (&admin.token, req.headers().get("X-Admin-Token").and_then(|v| v.to_str().ok())) {
+		(Some(expected), Some(given)) => (expected, given),
+		_ => return false,
+	};
+	// Digests are compared so that the time taken says nothing of the token.
+	Sha256::digest(expected.as_bytes()) == Sha256::digest(given.as_bytes())
+}
 
 pub async fn get_tags(
 	tags_client: web::Data<TagsClient>,
@@ -11,4 +66,103 @@ pub async fn get_tags(
 	let tags_client = tags_client.as_ref().as_ref().unwrap();
 	let tags = tags::dump_tags(tags_client).await?;
 	Ok(HttpResponse::Ok().json(tags))
-}
\ No newline at end of file
+}
+
+pub async fn put_tag(req: HttpRequest, path: web::Path<(String,)>, body: web::Json<TagBody>, admin: web::Data<Admin>, tags_client: web::Data<TagsClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	if !tags::valid_mercacc(&path.0) {
+		return Ok(respond(400, "Invalid merchant account"));
+	}
+	let tags_client = tags_client.as_ref().as_ref().unwrap();
+	if !tags::load_categories(tags_client)?.contains(&body.tag) {
+		return Ok(respond(400, "Invalid tag"));
+	}
+	tags::set_tag(tags_client, &path.0, &body.tag)?;
+	Ok(respond(200, "Success"))
+}
+
+pub async fn delete_tag(req: HttpRequest, path: web::Path<(String,)>, admin: web::Data<Admin>, tags_client: web::Data<TagsClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	if !tags::valid_mercacc(&path.0) {
+		return Ok(respond(400, "Invalid merchant account"));
+	}
+	match tags::remove_tag(tags_client.as_ref().as_ref().unwrap(), &path.0)? {
+		true => Ok(respond(200, "Success")),
+		false => Ok(respond(404, "Tag not found")),
+	}
+}
+
+/// Sets every tag of a `tags.json` style list, or none of them if one is
+/// invalid. Merchants left out keep their tag.
+pub async fn import_tags(req: HttpRequest, body: web::Json<Vec<Tag>>, admin: web::Data<Admin>, tags_client: web::Data<TagsClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	let tags_client = tags_client.as_ref().as_ref().unwrap();
+	let categories = tags::load_categories(tags_client)?;
+	if let Some(t) = body.iter().find(|t| !tags::valid_mercacc(&t.mercacc) || !categories.contains(&t.tag)) {
+		return Ok(respond(400, &format!("Invalid tag {} for merchant {}", t.tag, t.mercacc)));
+	}
+	if !body.is_empty() {
+		tags::import_tags(tags_client, &body)?;
+	}
+	Ok(HttpResponse::Ok().json(TagResponse{
+		status: 200,
+		msg: "Success".to_string(),
+		count: Some(body.len()),
+	}))
+}
+
+/// Merchants the worker found no tag for, most met first.
+pub async fn get_untagged(req: HttpRequest, query: web::Query<UntaggedQuery>, admin: web::Data<Admin>, untagged_client: web::Data<UntaggedClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	let data = tags::list_untagged(&untagged_client, query.limit.unwrap_or(DEFAULT_UNTAGGED_LIMIT))?;
+	Ok(HttpResponse::Ok().json(UntaggedResponse{
+		status: 200,
+		msg: "Success".to_string(),
+		data,
+	}))
+}
+
+/// Tags a merchant waiting for review, taking it off the list.
+pub async fn promote_untagged(req: HttpRequest, path: web::Path<(String,)>, body: web::Json<TagBody>, admin: web::Data<Admin>, tags_client: web::Data<TagsClient>, untagged_client: web::Data<UntaggedClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	let tags_client = tags_client.as_ref().as_ref().unwrap();
+	if !tags::load_categories(tags_client)?.contains(&body.tag) {
+		return Ok(respond(400, "Invalid tag"));
+	}
+	match tags::promote(tags_client, &untagged_client, &path.0, &body.tag)? {
+		true => Ok(respond(200, "Success")),
+		false => Ok(respond(404, "Merchant not waiting for review")),
+	}
+}
+
+/// The category tree tags are taken from.
+pub async fn get_categories(
+	tags_client: web::Data<TagsClient>,
+) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let categories = tags::load_categories(tags_client.as_ref().as_ref().unwrap())?;
+	Ok(HttpResponse::Ok().json(categories.tree()))
+}
+
+/// Replaces the category tree. Merchants keep their tag even if the tree no
+/// longer has its category; reports still count it under its own path.
+pub async fn put_categories(req: HttpRequest, body: web::Json<Vec<Category>>, admin: web::Data<Admin>, tags_client: web::Data<TagsClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if !is_admin(&req, &admin) {
+		return Ok(respond(401, "Invalid admin token"));
+	}
+	let categories = match Categories::new(body.into_inner()) {
+		Ok(categories) => categories,
+		Err(e) => return Ok(respond(400, &e.to_string())),
+	};
+	tags::set_categories(tags_client.as_ref().as_ref().unwrap(), categories.tree())?;
+	Ok(respond(200, "Success"))
+}
@@ -0,0 +1,121 @@
+use serde::{Serialize, Deserialize};
+use super::super::model::report::MAX_RANGE_DAYS;
+use super::super::model::tags;
+use super::super::model::transactions::{Cursor, Filter, TransactionPage, list_transactions};
+use super::super::model::session::Sessions;
+use ledger_core::ecard::EcardError;
+use ledger_core::store::transactions::StoreError;
+use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use chrono::{Datelike as _, NaiveDate};
+use mongodb::Client as MongoClient;
+use redis::Client as RedisClient;
+
+type TagsClient = Option<RedisClient>;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+pub struct Transactions{
+	status: i32,
+	msg: String,
+	data: Option<TransactionPage>,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionsQuery{
+	from: Option<String>,
+	to: Option<String>,
+	tag: Option<String>,
+	merchant: Option<String>,
+	min_amount: Option<f64>,
+	max_amount: Option<f64>,
+	limit: Option<usize>,
+	cursor: Option<String>,
+}
+
+fn bad_request(msg: &str) -> HttpResponse {
+	HttpResponse::BadRequest().json(Transactions{
+		status: 400,
+		msg: msg.to_string(),
+		data: None,
+	})
+}
+
+pub async fn get_transactions(req: HttpRequest, query: web::Query<TransactionsQuery>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>, tags_client: web::Data<TagsClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let session = match sessions.authenticate(&req, &redis_client)? {
+		Some(session) => session,
+		None => return Ok(HttpResponse::Unauthorized().json(Transactions{
+			status: 401,
+			msg: "Invalid or expired session".to_string(),
+			data: None,
+		})),
+	};
+
+	let today = chrono::Local::now().date_naive();
+	let parse_date = |s: &Option<String>, default: NaiveDate| match s {
+		Some(s) => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok(),
+		None => Some(default),
+	};
+	let (from, to) = match (parse_date(&query.from, today.with_day(1).unwrap()), parse_date(&query.to, today)) {
+		(Some(from), Some(to)) if from <= to && (to - from).num_days() < MAX_RANGE_DAYS => (from, to),
+		_ => return Ok(bad_request("Invalid date range")),
+	};
+	let tags_client = tags_client.as_ref().as_ref().unwrap();
+	if let Some(tag) = &query.tag {
+		if !tags::load_categories(tags_client)?.contains(tag) {
+			return Ok(bad_request("Invalid tag"));
+		}
+	}
+	let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+	if limit == 0 || limit > MAX_LIMIT {
+		return Ok(bad_request("Invalid limit"));
+	}
+	let cursor = match &query.cursor {
+		Some(c) => match Cursor::decode(c) {
+			Some(c) => Some(c),
+			None => return Ok(bad_request("Invalid cursor")),
+		},
+		None => None,
+	};
+	let filter = Filter {
+		from,
+		to,
+		tag: query.tag.clone(),
+		merchant: query.merchant.clone(),
+		min_amount: query.min_amount,
+		max_amount: query.max_amount,
+	};
+
+	match list_transactions(&session.castgc, &session.account, &filter, cursor, limit, &mongo_client, tags_client).await {
+		Ok(page) => Ok(HttpResponse::Ok().json(Transactions{
+			status: 200,
+			msg: "Success".to_string(),
+			data: Some(page),
+		})),
+		Err(e) if matches!(e.downcast_ref::<EcardError>(), Some(EcardError::SessionExpired)) => {
+			sessions.expire(&req, &redis_client)?;
+			Ok(HttpResponse::Unauthorized().json(Transactions{
+				status: 401,
+				msg: e.to_string(),
+				data: None,
+			}))
+		},
+		// The card system dropped its session again right after a new one.
+		Err(e) if matches!(e.downcast_ref::<EcardError>(), Some(EcardError::CardSessionExpired)) => Ok(HttpResponse::ServiceUnavailable().json(Transactions{
+			status: 503,
+			msg: e.to_string(),
+			data: None,
+		})),
+		Err(e) if matches!(e.downcast_ref::<StoreError>(), Some(StoreError::Parse(_))) => Ok(HttpResponse::BadGateway().json(Transactions{
+			status: 502,
+			msg: e.to_string(),
+			data: None,
+		})),
+		Err(e) => Ok(HttpResponse::InternalServerError().json(Transactions{
+			status: 500,
+			msg: e.to_string(),
+			data: None,
+		})),
+	}
+}
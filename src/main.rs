@@ -15,6 +15,8 @@ type TagsClient = Option<RedisClient>;
 async fn main() -> std::io::Result<()> {
 
     let config = config::config::init_config().await;
+    ledger_core::upstream::init(config.upstream.clone());
+    ledger_core::vault::init(&config.credentials);
 
 
     env_logger::init_from_env(Env::default().default_filter_or("info"));
@@ -23,7 +25,12 @@ async fn main() -> std::io::Result<()> {
     let mongo_client = MongoClient::with_options(mongo_client_options).unwrap();
     let redis_client = RedisClient::open(config.redis.url.as_str()).unwrap();
     let tags_client: TagsClient = Some(RedisClient::open(config.tags_db.url.as_str()).unwrap());
+    let untagged_client = web::Data::new(model::tags::UntaggedClient(RedisClient::open(config.untagged_db.url.as_str()).unwrap()));
 
+    let recognizer = web::Data::from(utils::ocr::recognizer(config.login.recognizer)?);
+    let sessions = web::Data::new(model::session::Sessions::new(&config.session));
+    let login = config.login;
+    let admin = config.admin;
     let server_host = config.server.host;
     let server_port = config.server.port;
 
@@ -32,6 +39,11 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(mongo_client.clone()))
             .app_data(web::Data::new(redis_client.clone()))
             .app_data(web::Data::new(tags_client.clone()))
+            .app_data(untagged_client.clone())
+            .app_data(web::Data::new(login.clone()))
+            .app_data(web::Data::new(admin.clone()))
+            .app_data(recognizer.clone())
+            .app_data(sessions.clone())
             .wrap(Logger::new("%{r}a %r %s"))
             .configure(router::router::config)
     });
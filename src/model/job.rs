@@ -0,0 +1,79 @@
+use serde::Serialize;
+use redis::Commands;
+use std::collections::HashMap;
+use ledger_core::keys;
+use ledger_core::model::job::Job;
+
+/// Progress of a job, as recorded in `job:{id}` by the API and the worker.
+#[derive(Serialize, Debug)]
+pub struct JobStatus {
+    pub id: String,
+    /// One of `queued`, `processing`, `retrying`, `finished` and `failed`.
+    pub state: String,
+    #[serde(skip)]
+    pub account: String,
+    pub period: String,
+    pub enqueued_at: Option<i64>,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub attempts: u32,
+    /// Month (`YYYYMM`) being fetched from the card system.
+    pub month: Option<String>,
+    /// Last page fetched for that month.
+    pub page: Option<u32>,
+    /// Next page announced by the card system, 0 once the last page is reached.
+    pub nextpage: Option<u32>,
+    /// Previous month reports generated along the way.
+    pub months_backfilled: u32,
+    pub error: Option<String>,
+    pub error_code: Option<String>,
+}
+
+fn num<T: std::str::FromStr>(fields: &HashMap<String, String>, name: &str) -> Option<T> {
+    fields.get(name).and_then(|v| v.parse().ok())
+}
+
+pub fn get_status(con: &mut redis::Connection, id: &str) -> Result<Option<JobStatus>, Box<dyn std::error::Error>> {
+    let mut fields: HashMap<String, String> = con.hgetall(keys::job_status(id))?;
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(JobStatus {
+        id: id.to_string(),
+        enqueued_at: num(&fields, "enqueued_at"),
+        started_at: num(&fields, "started_at"),
+        finished_at: num(&fields, "finished_at"),
+        attempts: num(&fields, "attempts").unwrap_or(0),
+        page: num(&fields, "page"),
+        nextpage: num(&fields, "nextpage"),
+        months_backfilled: num(&fields, "months_backfilled").unwrap_or(0),
+        state: fields.remove("state").unwrap_or_default(),
+        account: fields.remove("account").unwrap_or_default(),
+        period: fields.remove("period").unwrap_or_default(),
+        month: fields.remove("month"),
+        error: fields.remove("error"),
+        error_code: fields.remove("error_code"),
+    }))
+}
+
+/// Queues the job unless one for the same account and period is already
+/// pending, in which case `false` is returned.
+pub fn enqueue(con: &mut redis::Connection, job: &Job) -> Result<bool, Box<dyn std::error::Error>> {
+    let key = keys::request(&job.account, &job.period);
+    let created: bool = redis::cmd("SET").arg(&key).arg(&job.id).arg("NX").arg("EX").arg(keys::REQUEST_TTL)
+        .query::<Option<String>>(con)?.is_some();
+    if created {
+        let status = keys::job_status(&job.id);
+        let _: () = redis::pipe()
+            .hset_multiple(&status, &[
+                ("state", "queued".to_string()),
+                ("account", job.account.clone()),
+                ("period", job.period.clone()),
+                ("enqueued_at", job.enqueued_at.to_string()),
+            ]).ignore()
+            .expire(&status, keys::STATUS_TTL).ignore()
+            .rpush(keys::PENDING, serde_json::to_string(job)?).ignore()
+            .query(con)?;
+    }
+    Ok(created)
+}
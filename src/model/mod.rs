@@ -1,2 +1,5 @@
+pub mod job;
 pub mod report;
-pub mod tags;
\ No newline at end of file
+pub mod session;
+pub mod tags;
+pub mod transactions;
\ No newline at end of file
@@ -1,98 +1,108 @@
-use serde::{Serialize, Deserialize};
 use actix_web::web;
 use redis::Commands;
 use mongodb::{Client as MongoClient, Collection, bson::doc};
 use redis::Client as RedisClient;
+use chrono::NaiveDate;
+use ledger_core::keys;
+use ledger_core::vault;
+use ledger_core::model::report::ReportData;
+use ledger_core::model::job::{Job, JobError, JobResult, ErrorCode};
+use super::job::enqueue;
 
+/// Longest window accepted for a custom range report or a transaction list,
+/// in days.
+pub const MAX_RANGE_DAYS: i64 = 366;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Expense {
-    time: String,
-    location: String,
-    amount: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Trend {
-    count: i32,
-    expense: f64,
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Meal {
-    count: i32,
-    amount: f64,
+pub enum Status{
+	Created(String),
+	Processing(String),
+	Finished(ReportData),
+	Failed(JobError),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Trans{
-    location: String,
-    amount: f64,
-    count: i32,
+/// Whether a custom range is ordered, over by today and at most
+/// `MAX_RANGE_DAYS` long.
+fn valid_range(from: NaiveDate, to: NaiveDate) -> bool {
+    from <= to && to <= chrono::Local::now().date_naive() && (to - from).num_days() < MAX_RANGE_DAYS
 }
 
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ReportData {
-    date: String,
-    balance: f64,
-    total_expense: f64,
-    total_topup: f64,
-    total_count: i32,
-    top_expense: Expense,
-    top_count: Trans,
-    trend: [Trend; 3],
-    cafeteria_count: i32,
-    cafeteria_amount: f64,
-    groceries_count: i32,
-    groceries_amount: f64,
-    logistics_count: i32,
-    logistics_amount: f64,
-    other_count: i32,
-    other_amount: f64,
-    breakfast: Meal,
-    lunch: Meal,
-    dinner: Meal,
-    midnight_snack: Meal,
+/// Builds the `range_<from>_<to>` period used as queue and result key for a
+/// custom date range, with both bounds given as `YYYY-MM-DD`.
+pub fn range_period(from: &str, to: &str) -> Option<String> {
+    let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").ok()?;
+    let to = NaiveDate::parse_from_str(to, "%Y-%m-%d").ok()?;
+    if !valid_range(from, to) {
+        return None;
+    }
+    Some(format!("range_{}_{}", from.format("%Y%m%d"), to.format("%Y%m%d")))
 }
 
-pub enum Status{
-	Created,
-	Processing,
-	Finished(ReportData),
-	Error(Box<dyn std::error::Error>),
+/// Whether a period can be queued. `range_` periods reaching `/report/{period}`
+/// directly are held to the bounds of `range_period`.
+fn valid_period(period: &str) -> bool {
+    match period {
+        "week" | "month" | "semester" | "year" => true,
+        _ => period.strip_prefix("range_")
+            .and_then(|r| r.split_once('_'))
+            .and_then(|(from, to)| Some((NaiveDate::parse_from_str(from, "%Y%m%d").ok()?, NaiveDate::parse_from_str(to, "%Y%m%d").ok()?)))
+            .is_some_and(|(from, to)| valid_range(from, to)),
+    }
 }
 
 pub async fn get_report(account_no: String, period: &str, castgc: &str, redis_client: web::Data<RedisClient>, mongo_client: web::Data<MongoClient>) -> Result<Status, Box<dyn std::error::Error>> {
     let mut con = redis_client.get_connection()?;
-    let key = format!("request:{}:{}", account_no, period);
-    match con.get::<_, String>(&key) {
-        Ok(_) => {
-            Ok(Status::Processing)
+    let key = keys::request(&account_no, period);
+    match con.get::<_, Option<String>>(&key)? {
+        Some(id) if Job::valid_id(&id) => {
+            Ok(Status::Processing(id))
         },
-        Err(_) => {
-            let key_res = format!("result:{}:{}", account_no, period);
-            match con.get::<_, String>(&key_res) {
-                Ok(v) => {
-                    if v.starts_with("error") {
+        stale => {
+            // Keys of the polling queue hold `waiting:<CASTGC>` and are never
+            // released, the request is queued again.
+            if stale.is_some() {
+                let _: () = con.del(&key)?;
+            }
+            let key_res = keys::result(&account_no, period);
+            if let Ok(v) = con.get::<_, String>(&key_res) {
+                let report = match serde_json::from_str::<JobResult>(&v) {
+                    Ok(JobResult::Finished { report }) => Some(report),
+                    Ok(JobResult::Failed { error, castgc: queued_with }) => {
+                        let _: () = con.del(&key_res)?;
+                        // An expired CASTGC only ends the session the job was
+                        // queued with, a later login queues the request again.
+                        let same_session = queued_with.is_some_and(|sealed| vault::get().open(&sealed, &account_no).is_ok_and(|c| c == castgc));
+                        if error.code != ErrorCode::SessionExpired || same_session {
+                            return Ok(Status::Failed(error));
+                        }
+                        None
+                    },
+                    // Results written before they were serialized
+                    Err(_) if v.starts_with("error:") => {
                         let _: () = con.del(&key_res)?;
-                        return Ok(Status::Error(Box::new(std::io::Error::new(std::io::ErrorKind::Other, v.replace("error:", "")))));
-                    };
-                    let path = v.split("/").collect::<Vec<&str>>();
-                    println!("{:?}", path);
+                        return Ok(Status::Failed(JobError::new(ErrorCode::Internal, v["error:".len()..].trim().to_string())));
+                    },
+                    Err(_) => Some(v),
+                };
+                if let Some(report) = report {
+                    let path = report.split("/").collect::<Vec<&str>>();
                     let db = mongo_client.database(path[0]);
                     let collection: Collection<ReportData> = db.collection(path[1]);
                     let report = collection.find_one(doc!{"_id": mongodb::bson::oid::ObjectId::parse_str(&path[2])?}).await?.unwrap();
-                    Ok(Status::Finished(report))
-                },
-                Err(_) => {
-                    if period != "week" && period != "month" {
-                        return Ok(Status::Error(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Invalid period"))));
-                    }
-                    let _: () = con.set(&key, "waiting:".to_string()+castgc)?;
-                    Ok(Status::Created)
+                    return Ok(Status::Finished(report));
                 }
             }
+            if !valid_period(period) {
+                return Ok(Status::Failed(JobError::new(ErrorCode::InvalidPeriod, format!("Invalid period: {}", period))));
+            }
+            let job = Job::new(account_no, period.to_string(), castgc);
+            match enqueue(&mut con, &job)? {
+                true => Ok(Status::Created(job.id)),
+                false => {
+                    let id: Option<String> = con.get(&key)?;
+                    Ok(Status::Processing(id.filter(|id| Job::valid_id(id)).unwrap_or_default()))
+                },
+            }
         }
     }
 }
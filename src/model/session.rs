@@ -0,0 +1,117 @@
+use actix_web::HttpRequest;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use redis::Commands;
+use sha2::Sha256;
+use std::collections::HashMap;
+use ledger_core::vault;
+use super::super::config::config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a session token stands for. The CASTGC never leaves the backend.
+pub struct Session {
+    pub castgc: String,
+    pub account: String,
+}
+
+/// Issues and checks the session tokens handed out by `/login`. A token is
+/// `{id}.{signature}`, the id naming the `session:{id}` hash in Redis, where
+/// the CASTGC is kept sealed by the vault.
+pub struct Sessions {
+    secret: Vec<u8>,
+    pub ttl: u64,
+}
+
+fn session_key(id: &str) -> String {
+    format!("session:{}", id)
+}
+
+impl Sessions {
+    pub fn new(settings: &config::Session) -> Self {
+        let secret = match &settings.secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                eprintln!("No session secret configured, sessions will not survive a restart");
+                rand::random::<[u8; 32]>().to_vec()
+            }
+        };
+        Self { secret, ttl: settings.ttl }
+    }
+
+    fn sign(&self, id: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).unwrap();
+        mac.update(id.as_bytes());
+        mac
+    }
+
+    /// Returns the id of a token whose signature is valid.
+    fn verify<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let (id, signature) = token.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.sign(id).verify_slice(&signature).ok()?;
+        Some(id)
+    }
+
+    /// Opens a session for a CASTGC, returning its token.
+    pub fn create(&self, con: &mut redis::Connection, castgc: &str, account: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let key = session_key(&id);
+        let _: () = redis::pipe()
+            .hset_multiple(&key, &[("castgc", vault::get().seal(castgc, account).as_str()), ("account", account)]).ignore()
+            .expire(&key, self.ttl as i64).ignore()
+            .query(con)?;
+        let signature = URL_SAFE_NO_PAD.encode(self.sign(&id).finalize().into_bytes());
+        Ok(format!("{}.{}", id, signature))
+    }
+
+    pub fn get(&self, con: &mut redis::Connection, token: &str) -> Result<Option<Session>, Box<dyn std::error::Error>> {
+        let id = match self.verify(token) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let mut fields: HashMap<String, String> = con.hgetall(session_key(id))?;
+        let (sealed, account) = match (fields.remove("castgc"), fields.remove("account")) {
+            (Some(sealed), Some(account)) => (sealed, account),
+            _ => return Ok(None),
+        };
+        // Sessions sealed with a key dropped since are gone.
+        match vault::get().open(&sealed, &account) {
+            Ok(castgc) => Ok(Some(Session { castgc, account })),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Ends a session, returning whether it was still open.
+    pub fn remove(&self, con: &mut redis::Connection, token: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.verify(token) {
+            Some(id) => Ok(con.del::<_, i32>(session_key(id))? == 1),
+            None => Ok(false),
+        }
+    }
+
+    /// The session of the `Authorization: Bearer` token of a request, if it
+    /// is valid and has not expired.
+    pub fn authenticate(&self, req: &HttpRequest, redis_client: &redis::Client) -> Result<Option<Session>, Box<dyn std::error::Error>> {
+        match bearer(req) {
+            Some(token) => self.get(&mut redis_client.get_connection()?, token),
+            None => Ok(None),
+        }
+    }
+
+    /// Ends the session of a request once the card system stopped accepting
+    /// its CASTGC, so that the client is told to log in again rather than
+    /// sending the card system an expired ticket on every poll.
+    pub fn expire(&self, req: &HttpRequest, redis_client: &redis::Client) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(token) = bearer(req) {
+            self.remove(&mut redis_client.get_connection()?, token)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn bearer(req: &HttpRequest) -> Option<&str> {
+    req.headers().get("Authorization")?
+        .to_str().ok()?
+        .strip_prefix("Bearer ")
+}
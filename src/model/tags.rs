@@ -1,16 +1,48 @@
 use redis::Commands;
 use serde::{Serialize, Deserialize};
-
+use std::collections::HashMap;
+use ledger_core::classifier::Classifier;
+use ledger_core::keys;
+use ledger_core::model::category::{Categories, Category};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Tag{
-	mercacc: String,
-	tag: String,
+	/// Merchant account, also accepted as a number as in `tags.json`.
+	#[serde(deserialize_with = "mercacc")]
+	pub mercacc: String,
+	pub tag: String,
+}
+
+fn mercacc<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+	match serde_json::Value::deserialize(deserializer)? {
+		serde_json::Value::String(s) => Ok(s),
+		serde_json::Value::Number(n) => Ok(n.to_string()),
+		v => Err(serde::de::Error::custom(format!("invalid merchant account {}", v))),
+	}
+}
+
+/// Client of the database holding the merchants waiting for review.
+pub struct UntaggedClient(pub redis::Client);
+
+/// A merchant the worker found no tag for.
+#[derive(Serialize, Debug)]
+pub struct Untagged{
+	pub mercacc: String,
+	pub mercname: String,
+	/// Transactions with it met while generating reports, each counted once.
+	pub hits: u64,
+	pub first_seen: i64,
+	pub last_seen: i64,
+}
+
+pub fn valid_mercacc(mercacc: &str) -> bool {
+	!mercacc.is_empty() && mercacc.chars().all(|c| c.is_ascii_digit())
 }
 
 pub async fn dump_tags(redis_client: &redis::Client) -> Result<Vec<Tag>, Box<dyn std::error::Error>> {
 	let mut con = redis_client.get_connection()?;
-	let keys: Vec<String> = con.scan()?.collect();
+	// Only merchant accounts, should other keys share the database.
+	let keys: Vec<String> = con.scan()?.filter(|key: &String| valid_mercacc(key)).collect();
 	let tags = keys.into_iter()
 		.map(|key| {
 			let tag: String = con.get(&key)?;
@@ -18,4 +50,89 @@ pub async fn dump_tags(redis_client: &redis::Client) -> Result<Vec<Tag>, Box<dyn
 		})
 		.collect::<Result<Vec<Tag>, redis::RedisError>>()?;
 	Ok(tags)
-}
\ No newline at end of file
+}
+
+/// The category tree tags are checked against, the top-level categories alone
+/// if it was never set.
+pub fn load_categories(redis_client: &redis::Client) -> Result<Categories, Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	match con.get::<_, Option<String>>(keys::CATEGORIES)? {
+		Some(categories) => Ok(Categories::from_json(&categories)?),
+		None => Ok(Categories::default()),
+	}
+}
+
+/// The merchant rules the worker tags with, none if they were never set.
+pub fn load_rules(redis_client: &redis::Client) -> Result<Classifier, Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	match con.get::<_, Option<String>>(keys::RULES)? {
+		Some(rules) => Ok(Classifier::from_json(&rules)?),
+		None => Ok(Classifier::default()),
+	}
+}
+
+/// Replaces the category tree. It is expected to be validated already.
+pub fn set_categories(redis_client: &redis::Client, tree: &[Category]) -> Result<(), Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	let _: () = con.set(keys::CATEGORIES, serde_json::to_string(tree)?)?;
+	Ok(())
+}
+
+pub fn set_tag(redis_client: &redis::Client, mercacc: &str, tag: &str) -> Result<(), Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	let _: () = con.set(mercacc, tag)?;
+	Ok(())
+}
+
+/// Removes the tag of a merchant, returning whether it had one.
+pub fn remove_tag(redis_client: &redis::Client, mercacc: &str) -> Result<bool, Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	let removed: i32 = con.del(mercacc)?;
+	Ok(removed == 1)
+}
+
+/// Sets the tags all at once. They are expected to be validated already.
+pub fn import_tags(redis_client: &redis::Client, tags: &[Tag]) -> Result<(), Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	let pairs: Vec<(&str, &str)> = tags.iter().map(|t| (t.mercacc.as_str(), t.tag.as_str())).collect();
+	let _: () = con.mset(&pairs)?;
+	Ok(())
+}
+
+/// The merchants waiting for review, most met first.
+pub fn list_untagged(untagged_client: &UntaggedClient, limit: usize) -> Result<Vec<Untagged>, Box<dyn std::error::Error>> {
+	let mut con = untagged_client.0.get_connection()?;
+	let mercaccs: Vec<String> = con.zrange(keys::UNTAGGED, 0, -1)?;
+	let mut untagged = Vec::with_capacity(mercaccs.len());
+	for mercacc in mercaccs {
+		let mut fields: HashMap<String, String> = con.hgetall(keys::untagged(&mercacc))?;
+		let num = |fields: &HashMap<String, String>, name: &str| fields.get(name).and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+		untagged.push(Untagged {
+			hits: num(&fields, "hits") as u64,
+			first_seen: num(&fields, "first_seen"),
+			last_seen: num(&fields, "last_seen"),
+			mercname: fields.remove("mercname").unwrap_or_default(),
+			mercacc,
+		});
+	}
+	untagged.sort_by(|a, b| b.hits.cmp(&a.hits).then(b.last_seen.cmp(&a.last_seen)));
+	untagged.truncate(limit);
+	Ok(untagged)
+}
+
+/// Tags a merchant waiting for review and takes it off the list, returning
+/// whether it was on it.
+pub fn promote(tags_client: &redis::Client, untagged_client: &UntaggedClient, mercacc: &str, tag: &str) -> Result<bool, Box<dyn std::error::Error>> {
+	let mut con = untagged_client.0.get_connection()?;
+	let listed: Option<f64> = con.zscore(keys::UNTAGGED, mercacc)?;
+	if listed.is_none() {
+		return Ok(false);
+	}
+	set_tag(tags_client, mercacc, tag)?;
+	let _: () = redis::pipe()
+		.zrem(keys::UNTAGGED, mercacc).ignore()
+		.del(keys::untagged(mercacc)).ignore()
+		.del(keys::untagged_seen(mercacc)).ignore()
+		.query(&mut con)?;
+	Ok(true)
+}
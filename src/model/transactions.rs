@@ -0,0 +1,222 @@
+use serde::{Serialize, Deserialize};
+use redis::Commands;
+use chrono::{Datelike as _, NaiveDate};
+use base64::{engine::general_purpose, Engine};
+use mongodb::Client as MongoClient;
+use ledger_core::classifier::Classifier;
+use ledger_core::ecard::{self, EcardError};
+use ledger_core::model::category;
+use ledger_core::model::transaction::Transaction;
+use ledger_core::store::transactions::{self as store, Source, StoreError};
+use std::sync::Mutex;
+use super::tags;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Item {
+    time: String,
+    mercacc: String,
+    mercname: String,
+    amount: f64,
+    topup: bool,
+    tag: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TransactionPage {
+    items: Vec<Item>,
+    next_cursor: Option<String>,
+}
+
+pub struct Filter {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub tag: Option<String>,
+    pub merchant: Option<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
+
+impl Filter {
+    /// Whether a transaction of the window matches the other filters.
+    fn matches(&self, t: &Item) -> bool {
+        if let Some(tag) = &self.tag {
+            if !t.tag.as_deref().is_some_and(|t| category::is_within(t, tag)) {
+                return false;
+            }
+        }
+        if let Some(merchant) = &self.merchant {
+            if &t.mercacc != merchant && !t.mercname.contains(merchant.as_str()) {
+                return false;
+            }
+        }
+        if self.min_amount.is_some_and(|min| t.amount < min) || self.max_amount.is_some_and(|max| t.amount > max) {
+            return false;
+        }
+        true
+    }
+}
+
+/// The last transaction returned, as its `occtime` and id in the local store.
+/// Transactions stored since cannot shift the pages that follow it.
+pub struct Cursor {
+    occtime: i64,
+    id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(format!("{}:{}", self.occtime, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Cursor> {
+        let raw = String::from_utf8(general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (occtime, id) = raw.split_once(':')?;
+        let occtime = occtime.parse().ok()?;
+        if id.is_empty() {
+            return None;
+        }
+        Some(Cursor { occtime, id: id.to_string() })
+    }
+
+    fn day(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(self.occtime.to_string().get(..8)?, "%Y%m%d").ok()
+    }
+}
+
+/// The card system reached with the CASTGC of the session. Its client is
+/// only built, through CAS, once a month has to be fetched.
+struct Card {
+    castgc: String,
+    account: String,
+    client: Mutex<Option<reqwest::Client>>,
+}
+
+impl Card {
+    /// The card system client, a new one when `fresh` or none was built yet.
+    async fn client(&self, fresh: bool) -> Result<reqwest::Client, EcardError> {
+        let built = self.client.lock().unwrap().clone();
+        match built {
+            Some(client) if !fresh => Ok(client),
+            _ => {
+                let client = ecard::get_client(&self.castgc).await?;
+                *self.client.lock().unwrap() = Some(client.clone());
+                Ok(client)
+            },
+        }
+    }
+}
+
+impl Source for Card {
+    /// Goes through CAS again once if the card system dropped its session.
+    async fn select(&self, date_status: &str, page: u32) -> Result<serde_json::Value, EcardError> {
+        match ecard::select(&self.client(false).await?, &self.account, date_status, page).await {
+            Err(EcardError::CardSessionExpired) => ecard::select(&self.client(true).await?, &self.account, date_status, page).await,
+            res => res,
+        }
+    }
+}
+
+/// Merchants without a tag get the one of the first matching rule, as in
+/// reports. Storing it and queueing the others for review is left to the
+/// worker.
+fn item(t: Transaction, classifier: &Classifier, con: &mut redis::Connection) -> Item {
+    let tag = if t.topup {
+        None
+    } else {
+        let tag = con.get::<_, Option<String>>(&t.mercacc).ok().flatten()
+            .or_else(|| classifier.classify(&t.mercacc, &t.mercname).map(str::to_string));
+        Some(tag.unwrap_or("OTH".to_string()))
+    };
+    Item {
+        time: t.occtime.to_string(),
+        mercacc: t.mercacc,
+        mercname: t.mercname,
+        amount: t.amount,
+        topup: t.topup,
+        tag,
+    }
+}
+
+/// Card system errors are returned as `EcardError` for the caller to tell an
+/// expired login apart.
+fn boxed(e: StoreError) -> Box<dyn std::error::Error> {
+    match e {
+        StoreError::Ecard(e) => Box::new(e),
+        e => Box::new(e),
+    }
+}
+
+/// Lists the transactions of the filter window from the local store, newest
+/// first, returning at most `limit` of them and a cursor to resume from when
+/// more may follow. Each month is brought up to date from the card system
+/// before it is read, so months past the last page asked for are not fetched.
+/// The month of a cursor was brought up to date for the page before, and
+/// months over are fetched only once, so most pages need no card system.
+pub async fn list_transactions(castgc: &str, account: &str, filter: &Filter, cursor: Option<Cursor>, limit: usize, db: &MongoClient, tags_client: &redis::Client) -> Result<TransactionPage, Box<dyn std::error::Error>> {
+    let mut con = tags_client.get_connection()?;
+    let classifier = tags::load_rules(tags_client)?;
+    let card = Card { castgc: castgc.to_string(), account: account.to_string(), client: Mutex::new(None) };
+    let first_month = filter.from.with_day(1).unwrap();
+    let last_day = match cursor.as_ref().and_then(Cursor::day) {
+        Some(day) => day.min(filter.to),
+        None => filter.to,
+    };
+    let synced = cursor.as_ref().and_then(Cursor::day).map(|day| day.with_day(1).unwrap());
+    let mut month = last_day.with_day(1).unwrap();
+    let mut position = cursor;
+    let mut items = Vec::new();
+
+    while month >= first_month {
+        if synced != Some(month) {
+            store::sync_month(&card, account, month, db).await.map_err(boxed)?;
+        }
+        let from = month.max(filter.from);
+        let to = (store::next_month(month) - chrono::Duration::days(1)).min(last_day);
+        let (lower, upper) = store::bounds(from, to);
+        // Read in batches, as the other filters leave out an unknown share.
+        loop {
+            let before = position.as_ref().map(|c| (c.occtime, c.id.as_str()));
+            let batch = store::find_before(db, account, lower, upper, before, Some(limit as i64 + 1)).await.map_err(boxed)?;
+            let last = batch.len() <= limit;
+            for t in batch {
+                position = Some(Cursor { occtime: t.occtime, id: t.id.clone() });
+                let t = item(t, &classifier, &mut con);
+                if filter.matches(&t) {
+                    items.push(t);
+                    if items.len() == limit {
+                        return Ok(TransactionPage { items, next_cursor: position.as_ref().map(Cursor::encode) });
+                    }
+                }
+            }
+            if last {
+                break;
+            }
+        }
+        month = (month - chrono::Duration::days(1)).with_day(1).unwrap();
+    }
+    Ok(TransactionPage { items, next_cursor: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor { occtime: 20261018123045, id: "6712f0c2a1b2c3d4e5f60718".to_string() };
+        let decoded = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!((decoded.occtime, decoded.id.as_str()), (cursor.occtime, cursor.id.as_str()));
+        assert_eq!(decoded.day(), NaiveDate::from_ymd_opt(2026, 10, 18));
+    }
+
+    #[test]
+    fn malformed_cursor() {
+        let encode = |raw: &str| general_purpose::URL_SAFE_NO_PAD.encode(raw);
+        assert!(Cursor::decode("not base64!").is_none());
+        assert!(Cursor::decode(&general_purpose::URL_SAFE_NO_PAD.encode([0xff, 0xfe, b':', b'a'])).is_none());
+        assert!(Cursor::decode(&encode("20261018123045")).is_none());
+        assert!(Cursor::decode(&encode("20261018123045:")).is_none());
+        assert!(Cursor::decode(&encode("yesterday:6712f0c2")).is_none());
+        assert!(Cursor::decode(&encode(":6712f0c2")).is_none());
+    }
+}
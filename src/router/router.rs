@@ -5,12 +5,33 @@ use actix_web::web;
 pub fn config(cfg: &mut web::ServiceConfig) {
 	cfg.service(
 	web::scope("/login")
-			.route("", web::post().to(utils::hust_login::login)));
+			.route("", web::post().to(utils::hust_login::login))
+			.route("/captcha", web::post().to(utils::hust_login::login_captcha))
+			.route("/verify", web::post().to(utils::hust_login::verify)));
+	cfg.service(
+		web::scope("/logout").route("", web::post().to(utils::hust_login::logout))
+	);
+	cfg.service(
+		web::scope("/report/range").route("", web::get().to(controller::report::range_report))
+	);
 	cfg.service(
 		web::scope("/report/{period}").route("", web::get().to(controller::report::report))
 	);
+	cfg.service(
+		web::scope("/jobs/{id}").route("", web::get().to(controller::jobs::get_job))
+	);
+	cfg.service(
+		web::scope("/transactions").route("", web::get().to(controller::transactions::get_transactions))
+	);
 	cfg.service(
 		web::scope("/tags")
 			.route("", web::get().to(controller::tags::get_tags))
+			.route("/import", web::post().to(controller::tags::import_tags))
+			.route("/categories", web::get().to(controller::tags::get_categories))
+			.route("/categories", web::put().to(controller::tags::put_categories))
+			.route("/untagged", web::get().to(controller::tags::get_untagged))
+			.route("/untagged/{mercacc}", web::post().to(controller::tags::promote_untagged))
+			.route("/{mercacc}", web::put().to(controller::tags::put_tag))
+			.route("/{mercacc}", web::delete().to(controller::tags::delete_tag))
 	);
 }
\ No newline at end of file
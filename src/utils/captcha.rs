@@ -1,23 +1,41 @@
-use regex::Regex;
 use super::ocr;
+use super::login_form::LoginForm;
+use actix_web::web::Bytes;
+use captcha_ocr::{Captcha, Recognizer};
 use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use ledger_core::upstream;
 
 
-pub async fn get_captcha() -> Result<(String, String, String), Box<dyn std::error::Error>> {
-	let re_ticket = Regex::new(r#"<input type="hidden" id="lt" name="lt" value="(.*)" />"#).unwrap();
-	let request_url = "https://pass.hust.edu.cn/cas/login";
-	let captcha_url = "https://pass.hust.edu.cn/cas/code";
+/// A CAS login page, identified by the JSESSIONID it was served with.
+#[derive(Serialize, Deserialize)]
+pub struct LoginPage {
+	pub jsession: String,
+	pub form: LoginForm,
+}
 
-	let client = Client::builder()
-		.cookie_store(true)
-		.build()?;
-	let res = client.get(request_url).send().await?;
-	let jsession = res.cookies().find(|c| c.name() == "JSESSIONID").unwrap().value().to_string();
-	let body = res.text().await.unwrap();
-	let ticket = re_ticket.captures(&body).unwrap().get(1).unwrap().as_str();
+/// Opens the CAS login page, leaving its JSESSIONID in the client's cookie
+/// store, and returns its login form.
+pub async fn get_login_page(client: &Client) -> Result<LoginPage, Box<dyn std::error::Error>> {
+	let res = client.get(upstream::get().cas_url("/cas/login")).send().await?;
+	let jsession = match res.cookies().find(|c| c.name() == "JSESSIONID") {
+		Some(c) => c.value().to_string(),
+		None => return Err(Box::new(std::io::Error::other("Session cookie not found"))),
+	};
+	let url = res.url().clone();
+	let body = res.text().await?;
+	let form = LoginForm::parse(&body, &url)?;
+	Ok(LoginPage { jsession, form })
+}
 
-	let captcha = client.get(captcha_url).send().await?;
-	let img = captcha.bytes().await.unwrap();
-	let code = ocr::decode_captcha(img).await?;
-	Ok((ticket.to_string(), jsession, code))
-}
\ No newline at end of file
+/// Fetches a new captcha GIF for the session of the client.
+pub async fn get_captcha_image(client: &Client) -> Result<Bytes, Box<dyn std::error::Error>> {
+	let captcha = client.get(upstream::get().cas_url("/cas/code")).send().await?;
+	Ok(captcha.bytes().await?)
+}
+
+/// Fetches a new captcha for the session of the client and reads it.
+pub async fn get_captcha(client: &Client, recognizer: &dyn Recognizer) -> Result<Captcha, Box<dyn std::error::Error>> {
+	let img = get_captcha_image(client).await?;
+	ocr::decode_captcha(img, recognizer).await
+}
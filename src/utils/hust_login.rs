@@ -1,16 +1,40 @@
-use actix_web::{web, HttpResponse, Responder};
-use reqwest::{Client, header};
+use actix_web::{web, http::StatusCode, HttpRequest, HttpResponse, Responder};
+use reqwest::Client;
+use ledger_core::ecard::default_headers;
+use ledger_core::upstream;
+use super::captcha::{get_captcha, get_captcha_image, get_login_page, LoginPage};
+use super::login_form::{self, LoginForm, PageChanged};
+use super::super::config::config::Login;
+use super::super::model::session::{bearer, Sessions};
+use ledger_core::ecard::get_account_no;
+use ledger_core::vault;
+use captcha_ocr::Recognizer;
 use serde::{Serialize, Deserialize};
 use serde_json;
 use base64::{engine::general_purpose, Engine};
 use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
 use rand;
 use rsa::pkcs8::DecodePublicKey;
+use redis::{Client as RedisClient, Commands};
+use std::sync::Arc;
+
+/// Seconds a captcha handed out by `/login/captcha` can be answered in.
+const CAPTCHA_TTL: u64 = 300;
+/// Seconds an SMS verification started by `/login` can be completed in.
+const VERIFY_TTL: u64 = 300;
+/// Error CAS shows for a wrong captcha answer.
+const CAPTCHA_ERROR: &str = "验证码错误";
+/// Input of the SMS code, hidden on the password step of CAS and shown on its
+/// SMS verification step.
+const PHONE_CODE: &str = "phoneCode";
 
 #[derive(Deserialize)]
 pub struct Credential {
 	username: String,
 	password: String,
+	/// Token of a captcha from `/login/captcha`, answered with `code`.
+	token: Option<String>,
+	code: Option<String>,
 }
 
 
@@ -18,87 +42,295 @@ pub struct Credential {
 struct Info{
 	status: i32,
 	msg: String,
-	castgc: String,
-}
-
-pub async fn get_account_no(castgc: &str) -> Result<String, Box<dyn std::error::Error>>{
-	let url = "http://ecard.m.hust.edu.cn/wechat-web/QueryController/Queryurl.html";
-	let regex = regex::Regex::new(r#"<input id="account" type="hidden" value="(.*)"/>"#).unwrap();
-
-	let cookie_store = reqwest::cookie::Jar::default();
-	let url_token = reqwest::Url::parse("https://pass.hust.edu.cn").unwrap();
-	cookie_store.add_cookie_str(("CASTGC=".to_owned() + &castgc).as_str(), &url_token);
-
-	let client = Client::builder()
-	.cookie_provider(cookie_store.into())
-	.default_headers({
-		let mut headers = header::HeaderMap::new();
-		headers.insert(header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3".parse().unwrap());
-		headers.insert(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8".parse().unwrap());
-		headers.insert(header::ACCEPT_ENCODING, "gzip, deflate, sdch".parse().unwrap());
-		headers.insert(header::ACCEPT_LANGUAGE, "zh-CN,zh;q=0.8".parse().unwrap());
-		headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
-		headers
+}
+
+#[derive(Serialize)]
+struct LoggedIn{
+	status: i32,
+	msg: String,
+	token: String,
+	/// Seconds the session lasts.
+	expires_in: u64,
+}
+
+#[derive(Deserialize)]
+pub struct Verification {
+	/// Token of a `challenge_required` response from `/login`.
+	token: String,
+	/// The code sent by SMS.
+	code: String,
+}
+
+#[derive(Serialize)]
+struct CaptchaChallenge{
+	status: i32,
+	msg: String,
+	token: String,
+	/// The captcha GIF, base64 encoded.
+	image: String,
+}
+
+#[derive(Serialize)]
+struct VerifyChallenge{
+	status: i32,
+	msg: String,
+	/// Token to send the SMS code with through `/login/verify`.
+	token: String,
+}
+
+/// A login in progress: the CAS session, the encrypted credentials and the
+/// form to submit them with. Kept sealed by the vault while it waits for its
+/// SMS code.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+	jsession: String,
+	ul: String,
+	pl: String,
+	form: LoginForm,
+}
+
+/// Outcome of submitting the CAS login form.
+enum Submitted {
+	LoggedIn(String),
+	/// CAS asks for the code it sent by SMS before issuing a CASTGC.
+	PhoneCodeRequired(PendingLogin),
+	WrongCaptcha,
+	Rejected,
+}
+
+/// Logs in to CAS, reading the captcha with OCR. Readings that cannot be
+/// right are replaced by a new captcha before submitting, and captchas CAS
+/// rejects are retried with a new login page, up to `captcha_attempts`
+/// captchas in total. Accounts with SMS verification get a token to complete
+/// the login with through `/login/verify`.
+pub async fn login(cred: web::Json<Credential>, settings: web::Data<Login>, recognizer: web::Data<dyn Recognizer>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	if let Some(token) = &cred.token {
+		return manual_login(&cred, token, &sessions, &redis_client).await;
+	}
+	let mut attempts = 0;
+	while attempts < settings.captcha_attempts {
+		let client = cas_client(None)?;
+		let page = match get_login_page(&client).await{
+			Ok(page) => page,
+			Err(e) => return Ok(failure(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get captcha", e)),
+		};
+		let mut code = None;
+		while code.is_none() && attempts < settings.captcha_attempts {
+			attempts += 1;
+			let captcha = match get_captcha(&client, recognizer.as_ref()).await{
+				Ok(captcha) => captcha,
+				Err(e) => return Ok(failure(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get captcha", e)),
+			};
+			if captcha.is_well_formed() && captcha.confidence >= settings.min_confidence {
+				code = Some(captcha.text);
+			}
+		}
+		let code = match code {
+			Some(code) => code,
+			None => break,
+		};
+		match get_castgc(&cred, page, &code, &client).await{
+			Ok(Submitted::LoggedIn(castgc)) => return logged_in(&castgc, &sessions, &redis_client).await,
+			Ok(Submitted::PhoneCodeRequired(pending)) => return challenge(&redis_client, &pending),
+			Ok(Submitted::WrongCaptcha) => continue,
+			Ok(Submitted::Rejected) => return Ok(HttpResponse::Forbidden().json(Info{
+				status: 403,
+				msg: "Wrong username or password.".to_string(),
+			})),
+			Err(e) => return Ok(failure(StatusCode::FORBIDDEN, "Failed to login", e)),
+		}
+	}
+	Ok(HttpResponse::ServiceUnavailable().json(Info{
+		status: 503,
+		msg: format!("Failed to recognize the captcha in {} attempts, use /login/captcha.", settings.captcha_attempts),
+	}))
+}
+
+
+/// Response for a login step that failed with `e`: a 502 when CAS changed its
+/// login page, `status` otherwise.
+fn failure(status: StatusCode, context: &str, e: Box<dyn std::error::Error>) -> HttpResponse {
+	let status = if e.is::<PageChanged>() { StatusCode::BAD_GATEWAY } else { status };
+	HttpResponse::build(status).json(Info{
+		status: status.as_u16() as i32,
+		msg: format!("{}: {}", context, e),
 	})
-	.build()
-	.unwrap();
-
-	let res = client.get(url).send().await?;
-	let resp = res.text().await.unwrap();
-	let account_no = match regex.captures(resp.as_str()){
-		Some(caps) => match caps.get(1){
-			Some(cap) => cap.as_str(),
-			None => {return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "String match failed")));},
+}
+
+/// Opens a session for a CASTGC CAS just issued. The CASTGC stays on the
+/// server, the client gets a session token instead.
+async fn logged_in(castgc: &str, sessions: &Sessions, redis_client: &RedisClient) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+	let account = match get_account_no(castgc).await {
+		Ok(account) => account,
+		Err(e) => return Ok(HttpResponse::BadGateway().json(Info{
+			status: 502,
+			msg: format!("Failed to get the card account: {}", e),
+		})),
+	};
+	let token = sessions.create(&mut redis_client.get_connection()?, castgc, &account)?;
+	Ok(HttpResponse::Ok().json(LoggedIn{
+		status: 200,
+		msg: "Login successful.".to_string(),
+		token,
+		expires_in: sessions.ttl,
+	}))
+}
+
+/// Ends the session of the `Authorization: Bearer` token.
+pub async fn logout(req: HttpRequest, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let removed = match bearer(&req) {
+		Some(token) => sessions.remove(&mut redis_client.get_connection()?, token)?,
+		None => false,
+	};
+	if removed {
+		Ok(HttpResponse::Ok().json(Info{
+			status: 200,
+			msg: "Logged out.".to_string(),
+		}))
+	} else {
+		Ok(HttpResponse::Unauthorized().json(Info{
+			status: 401,
+			msg: "Invalid or expired session".to_string(),
+		}))
+	}
+}
+
+/// Client for the CAS login form, optionally resuming the session of a login
+/// page served before.
+fn cas_client(jsession: Option<&str>) -> Result<Client, reqwest::Error> {
+	let cookie_store = Arc::new(reqwest::cookie::Jar::default());
+	if let Some(jsession) = jsession {
+		let url = reqwest::Url::parse(&upstream::get().cas).unwrap();
+		cookie_store.add_cookie_str(("JSESSIONID=".to_owned() + jsession).as_str(), &url);
+	}
+	Client::builder()
+		.redirect(reqwest::redirect::Policy::none())
+		.cookie_provider(cookie_store)
+		.default_headers(default_headers())
+		.connect_timeout(upstream::CONNECT_TIMEOUT)
+		.read_timeout(upstream::READ_TIMEOUT)
+		.build()
+}
+
+fn captcha_key(token: &str) -> String {
+	format!("login:{}", token)
+}
+
+/// Hands out a captcha for a person to read, along with a token to answer it
+/// with through `POST /login`, for when OCR keeps failing.
+pub async fn login_captcha(redis_client: web::Data<RedisClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let client = cas_client(None)?;
+	let (page, image) = match get_login_page(&client).await{
+		Ok(page) => match get_captcha_image(&client).await{
+			Ok(image) => (page, image),
+			Err(e) => return Ok(failure(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get captcha", e)),
 		},
-		None => {return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Login expired.")));},
+		Err(e) => return Ok(failure(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get captcha", e)),
 	};
-	Ok(account_no.to_string())
+	let token = format!("{:032x}", rand::random::<u128>());
+	let mut con = redis_client.get_connection()?;
+	let _: () = con.set_ex(captcha_key(&token), serde_json::to_string(&page)?, CAPTCHA_TTL)?;
+	Ok(HttpResponse::Ok().json(CaptchaChallenge{
+		status: 200,
+		msg: "Success".to_string(),
+		token,
+		image: general_purpose::STANDARD.encode(&image),
+	}))
 }
 
-pub async fn login(cred: web::Json<Credential>) -> Result<impl Responder, Box<dyn std::error::Error>> {
-	let (lt, jsession, code) = match super::captcha::get_captcha().await{
-		Ok((lt, jsession, code)) => (lt, jsession, code),
-		Err(e) => return Ok(HttpResponse::InternalServerError().json(Info{
-			status: 500,
-			msg: format!("Failed to get captcha: {}", e),
-			castgc: "".to_string(),
+/// Submits a captcha from `/login/captcha` read by the user. Tokens are
+/// single use, a wrong code needs a new captcha.
+async fn manual_login(cred: &Credential, token: &str, sessions: &Sessions, redis_client: &RedisClient) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+	let code = match &cred.code {
+		Some(code) => code,
+		None => return Ok(HttpResponse::BadRequest().json(Info{
+			status: 400,
+			msg: "Missing captcha code".to_string(),
 		})),
 	};
-	let cookie_store = reqwest::cookie::Jar::default();
-	let url = reqwest::Url::parse("https://pass.hust.edu.cn").unwrap();
-	cookie_store.add_cookie_str(("JSESSIONID=".to_owned() + &jsession).as_str(), &url);
-	let mut client = Client::builder()
-		.redirect(reqwest::redirect::Policy::none())
-		.cookie_provider(cookie_store.into())
-		.default_headers({
-			let mut headers = header::HeaderMap::new();
-			headers.insert(header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3".parse().unwrap());
-			headers.insert(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8".parse().unwrap());
-			headers.insert(header::ACCEPT_ENCODING, "gzip, deflate, sdch".parse().unwrap());
-			headers.insert(header::ACCEPT_LANGUAGE, "zh-CN,zh;q=0.8".parse().unwrap());
-			headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
-			headers
-		})
-		.build()?;
-	let castgc = match get_castgc(cred, lt, code, &mut client).await{
-		Ok(castgc) => castgc,
-		Err(e) => return Ok(HttpResponse::Forbidden().json(Info{
-			status: 403,
-			msg: format!("Failed to login: {}", e),
-			castgc: "".to_string(),
+	let mut con = redis_client.get_connection()?;
+	let page: Option<String> = redis::cmd("GETDEL").arg(captcha_key(token)).query(&mut con)?;
+	let page: LoginPage = match page {
+		Some(page) => serde_json::from_str(&page)?,
+		None => return Ok(HttpResponse::BadRequest().json(Info{
+			status: 400,
+			msg: "Captcha expired, request a new one".to_string(),
 		})),
 	};
-	Ok(HttpResponse::Ok().json(Info{
-		status: 200,
-		msg: "Login successful.".to_string(),
-		castgc: castgc,
+	let client = cas_client(Some(&page.jsession))?;
+	match get_castgc(cred, page, code, &client).await{
+		Ok(Submitted::LoggedIn(castgc)) => logged_in(&castgc, sessions, redis_client).await,
+		Ok(Submitted::PhoneCodeRequired(pending)) => challenge(redis_client, &pending),
+		Ok(Submitted::WrongCaptcha) => Ok(HttpResponse::Forbidden().json(Info{
+			status: 403,
+			msg: "Wrong captcha.".to_string(),
+		})),
+		Ok(Submitted::Rejected) => Ok(HttpResponse::Forbidden().json(Info{
+			status: 403,
+			msg: "Wrong username or password.".to_string(),
+		})),
+		Err(e) => Ok(failure(StatusCode::FORBIDDEN, "Failed to login", e)),
+	}
+}
+
+fn verify_key(token: &str) -> String {
+	format!("verify:{}", token)
+}
+
+/// Keeps `pending` for `/login/verify`, sealed with its token as context.
+fn keep_pending(con: &mut redis::Connection, token: &str, pending: &PendingLogin) -> Result<(), Box<dyn std::error::Error>> {
+	let sealed = vault::get().seal(&serde_json::to_string(pending)?, token);
+	let _: () = con.set_ex(verify_key(token), sealed, VERIFY_TTL)?;
+	Ok(())
+}
+
+/// Keeps a login waiting for its SMS code and hands out the token to send the
+/// code with.
+fn challenge(redis_client: &RedisClient, pending: &PendingLogin) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+	let token = format!("{:032x}", rand::random::<u128>());
+	let mut con = redis_client.get_connection()?;
+	keep_pending(&mut con, &token, pending)?;
+	Ok(HttpResponse::Accepted().json(VerifyChallenge{
+		status: 202,
+		msg: "challenge_required".to_string(),
+		token,
 	}))
 }
 
+/// Completes a login CAS asked an SMS code for. A wrong code can be retried
+/// with the same token until it expires.
+pub async fn verify(form: web::Json<Verification>, sessions: web::Data<Sessions>, redis_client: web::Data<RedisClient>) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	let mut con = redis_client.get_connection()?;
+	let sealed: Option<String> = redis::cmd("GETDEL").arg(verify_key(&form.token)).query(&mut con)?;
+	// Logins sealed with a key dropped since are as good as expired.
+	let pending = sealed.and_then(|sealed| vault::get().open(&sealed, &form.token).ok());
+	let pending: PendingLogin = match pending {
+		Some(pending) => serde_json::from_str(&pending)?,
+		None => return Ok(HttpResponse::BadRequest().json(Info{
+			status: 400,
+			msg: "Verification expired, login again".to_string(),
+		})),
+	};
+	let client = cas_client(Some(&pending.jsession))?;
+	match submit(&client, pending, "", &form.code).await{
+		Ok(Submitted::LoggedIn(castgc)) => logged_in(&castgc, &sessions, &redis_client).await,
+		Ok(Submitted::PhoneCodeRequired(pending)) => {
+			keep_pending(&mut con, &form.token, &pending)?;
+			Ok(HttpResponse::Forbidden().json(Info{
+				status: 403,
+				msg: "Wrong verification code.".to_string(),
+			}))
+		},
+		Ok(Submitted::WrongCaptcha) | Ok(Submitted::Rejected) => Ok(HttpResponse::Forbidden().json(Info{
+			status: 403,
+			msg: "Verification rejected, login again.".to_string(),
+		})),
+		Err(e) => Ok(failure(StatusCode::FORBIDDEN, "Failed to login", e)),
+	}
+}
 
-async fn get_castgc(cred: web::Json<Credential>,lt: String, code: String, client: &mut Client) -> Result<String, Box<dyn std::error::Error>> {
-	let url = "https://pass.hust.edu.cn/cas/login?service=http%3A%2F%2Fecard.m.hust.edu.cn%3A80%2Fwechat-web%2FQueryController%2Fselect.html";
-	let rsa_url = "https://pass.hust.edu.cn/cas/rsa";
+async fn get_castgc(cred: &Credential, page: LoginPage, code: &str, client: &Client) -> Result<Submitted, Box<dyn std::error::Error>> {
+	let rsa_url = upstream::get().cas_url("/cas/rsa");
 
 	let rsa_res = client.post(rsa_url).send().await?;
 	let rsa_json: serde_json::Value = serde_json::from_str(&rsa_res.text().await.unwrap()).unwrap();
@@ -112,15 +344,94 @@ async fn get_castgc(cred: web::Json<Credential>,lt: String, code: String, client
 	let pl_vec = rsa.encrypt(&mut rng, Pkcs1v15Encrypt, cred.password.as_bytes()).unwrap();
 	let pl = general_purpose::STANDARD.encode(pl_vec.as_ref() as &[u8]);
 
-	let res = client.post(url)
-		.form(&[("ul", ul), ("pl", pl), ("lt", lt), ("code", code[..4].to_string()), 
-				("rsa", "".to_string()), ("phoneCode", "".to_string()), ("execution", "e1s1".to_string()), 
-				("_eventId", "submit".to_string())])
-		.send().await?;
-	let cookies = res.cookies().collect::<Vec<_>>();
-	match cookies.iter().find(|c| c.name() == "CASTGC"){
-		Some(t) => Ok(t.value().to_string()),
-		None => Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Wrong username, password or captcha."))),
+	let pending = PendingLogin { jsession: page.jsession, ul, pl, form: page.form };
+	submit(client, pending, code, "").await
+}
+
+/// Posts the CAS login form, telling the page CAS renders again apart when
+/// no CASTGC is issued.
+async fn submit(client: &Client, pending: PendingLogin, code: &str, phone_code: &str) -> Result<Submitted, Box<dyn std::error::Error>> {
+	let upstream = upstream::get();
+	let mut url = reqwest::Url::parse(&pending.form.action)?;
+	if !url.query_pairs().any(|(name, _)| name == "service") {
+		url.query_pairs_mut().append_pair("service", &upstream.ecard_url("/wechat-web/QueryController/select.html"));
+	}
+
+	let fields = pending.form.fields(&[("ul", pending.ul.clone()), ("pl", pending.pl.clone()),
+			("code", code.to_string()), (PHONE_CODE, phone_code.to_string())]);
+	let res = client.post(url).form(&fields).send().await?;
+	if let Some(t) = res.cookies().find(|c| c.name() == "CASTGC") {
+		return Ok(Submitted::LoggedIn(t.value().to_string()));
+	}
+	let url = res.url().clone();
+	let body = res.text().await?;
+	Ok(rendered_again(pending, &body, &url))
+}
+
+/// Tells apart the login page CAS rendered again instead of issuing a CASTGC.
+fn rendered_again(pending: PendingLogin, body: &str, url: &reqwest::Url) -> Submitted {
+	if let Ok(form) = LoginForm::parse(body, url) {
+		if form.asks(PHONE_CODE) {
+			return Submitted::PhoneCodeRequired(PendingLogin { form, ..pending });
+		}
+	}
+	// Matched from the start, as "短信验证码错误" of the SMS step contains it too.
+	match login_form::error_message(body) {
+		Some(msg) if msg.starts_with(CAPTCHA_ERROR) => Submitted::WrongCaptcha,
+		_ => Submitted::Rejected,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const LOGIN_PAGE: &str = include_str!("testdata/cas_login.html");
+	const SMS_PAGE: &str = include_str!("testdata/cas_sms.html");
+
+	fn submitted(body: &str) -> Submitted {
+		let pending = PendingLogin {
+			jsession: "CAS-1".to_string(),
+			ul: "U".to_string(),
+			pl: "P".to_string(),
+			form: LoginForm::parse(LOGIN_PAGE, &url()).unwrap(),
+		};
+		rendered_again(pending, body, &url())
+	}
+
+	fn url() -> reqwest::Url {
+		reqwest::Url::parse("https://pass.hust.edu.cn/cas/login").unwrap()
+	}
+
+	fn with_error(page: &str, error: &str) -> String {
+		page.replace(r#"<span id="errormsg" class="error"></span>"#, &format!(r#"<span id="errormsg" class="error">{}</span>"#, error))
+	}
+
+	#[test]
+	fn sms_step() {
+		match submitted(SMS_PAGE) {
+			Submitted::PhoneCodeRequired(pending) => {
+				assert_eq!(pending.form.get("execution"), Some("e1s2"));
+				assert_eq!(pending.form.get("lt"), Some("LT-654321-ZyXwVuTsRqPoNmLkJiHgFeDcBa-cas"));
+				assert_eq!((pending.jsession.as_str(), pending.ul.as_str()), ("CAS-1", "U"));
+			},
+			_ => panic!("SMS step not recognized"),
+		}
+		// A wrong SMS code renders the step again, "短信验证码错误" included.
+		assert!(SMS_PAGE.contains("短信验证码错误"));
 	}
 
+	#[test]
+	fn wrong_captcha() {
+		assert!(matches!(submitted(&with_error(LOGIN_PAGE, "验证码错误")), Submitted::WrongCaptcha));
+		assert!(matches!(submitted(&with_error(LOGIN_PAGE, " 验证码错误，请重新输入 ")), Submitted::WrongCaptcha));
+	}
+
+	#[test]
+	fn rejected() {
+		assert!(matches!(submitted(&with_error(LOGIN_PAGE, "用户名或密码错误")), Submitted::Rejected));
+		assert!(matches!(submitted(&with_error(LOGIN_PAGE, "短信验证码错误")), Submitted::Rejected));
+		assert!(matches!(submitted(LOGIN_PAGE), Submitted::Rejected));
+		assert!(matches!(submitted("<html><body>503 Service Unavailable</body></html>"), Submitted::Rejected));
+	}
 }
@@ -0,0 +1,184 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Serialize, Deserialize};
+
+/// Hidden fields the CAS login form cannot be submitted without.
+const REQUIRED: [&str; 3] = ["lt", "execution", "_eventId"];
+/// Where CAS shows why the form was rendered again.
+const ERROR: &str = "#errormsg, .error";
+
+/// The CAS login page no longer has the form login relies on.
+#[derive(Debug)]
+pub struct PageChanged(pub String);
+
+impl std::fmt::Display for PageChanged {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Upstream login page changed: {}", self.0)
+	}
+}
+
+impl std::error::Error for PageChanged {}
+
+/// The login form of a CAS page: where it posts to, its hidden fields in page
+/// order, and the names of the inputs meant to be filled in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LoginForm {
+	pub action: String,
+	pub hidden: Vec<(String, String)>,
+	pub visible: Vec<String>,
+}
+
+impl LoginForm {
+	/// Reads the login form of a page served at `url`, the one holding the
+	/// login ticket.
+	pub fn parse(html: &str, url: &Url) -> Result<LoginForm, PageChanged> {
+		let forms = Selector::parse("form").unwrap();
+		let inputs = Selector::parse("input[name]").unwrap();
+		let document = Html::parse_document(html);
+		let form = document.select(&forms)
+			.find(|form| form.select(&inputs).any(|input| input.value().attr("name") == Some("lt")))
+			.ok_or_else(|| PageChanged("login form not found".to_string()))?;
+
+		let action = match form.value().attr("action").map(str::trim) {
+			Some(action) if !action.is_empty() => url.join(action).map_err(|_| PageChanged(format!("invalid form action {}", action)))?,
+			_ => url.clone(),
+		};
+		let mut hidden = Vec::new();
+		let mut visible = Vec::new();
+		for input in form.select(&inputs) {
+			let name = input.value().attr("name").unwrap_or("").to_string();
+			if is_hidden(&input) {
+				hidden.push((name, input.value().attr("value").unwrap_or("").to_string()));
+			} else {
+				visible.push(name);
+			}
+		}
+
+		let form = LoginForm { action: action.to_string(), hidden, visible };
+		let missing: Vec<&str> = REQUIRED.iter().copied().filter(|name| form.get(name).is_none()).collect();
+		if !missing.is_empty() {
+			return Err(PageChanged(format!("missing {}", missing.join(", "))));
+		}
+		Ok(form)
+	}
+
+	/// Value of a hidden field.
+	pub fn get(&self, name: &str) -> Option<&str> {
+		self.hidden.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+	}
+
+	/// Whether the form asks the user to fill in `name`.
+	pub fn asks(&self, name: &str) -> bool {
+		self.visible.iter().any(|n| n == name)
+	}
+
+	/// The fields to submit: the hidden ones as served, with `values` replacing
+	/// or added to them.
+	pub fn fields(&self, values: &[(&str, String)]) -> Vec<(String, String)> {
+		let mut fields: Vec<(String, String)> = self.hidden.iter()
+			.map(|(name, value)| match values.iter().find(|(n, _)| n == name) {
+				Some((_, v)) => (name.clone(), v.clone()),
+				None => (name.clone(), value.clone()),
+			})
+			.collect();
+		for (name, value) in values {
+			if self.get(name).is_none() {
+				fields.push((name.to_string(), value.clone()));
+			}
+		}
+		fields
+	}
+}
+
+/// The error CAS shows on the login page it rendered again, if any.
+pub fn error_message(html: &str) -> Option<String> {
+	let errors = Selector::parse(ERROR).unwrap();
+	let document = Html::parse_document(html);
+	document.select(&errors)
+		.map(|e| e.text().collect::<String>().trim().to_string())
+		.find(|msg| !msg.is_empty())
+}
+
+fn is_hidden(input: &ElementRef) -> bool {
+	input.value().attr("type").map(|t| t.eq_ignore_ascii_case("hidden")).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const LOGIN_PAGE: &str = include_str!("testdata/cas_login.html");
+
+	fn url() -> Url {
+		Url::parse("https://pass.hust.edu.cn/cas/login?service=x").unwrap()
+	}
+
+	fn error(html: &str) -> String {
+		LoginForm::parse(html, &url()).unwrap_err().0
+	}
+
+	#[test]
+	fn login_page() {
+		let form = LoginForm::parse(LOGIN_PAGE, &url()).unwrap();
+		assert_eq!(form.action, "https://pass.hust.edu.cn/cas/login?service=http%3A%2F%2Fecard.m.hust.edu.cn%2Fwechat-web%2FQueryController%2Fselect.html");
+		let names: Vec<&str> = form.hidden.iter().map(|(name, _)| name.as_str()).collect();
+		assert_eq!(names, ["rsa", "ul", "pl", "phoneCode", "lt", "execution", "_eventId"]);
+		assert_eq!(form.get("lt"), Some("LT-123456-AbCdEfGhIjKlMnOpQrStUvWxYz-cas"));
+		assert_eq!(form.get("execution"), Some("e1s1"));
+		assert_eq!(form.visible, ["username", "password", "code"]);
+		assert!(!form.asks("phoneCode"));
+		assert_eq!(error_message(LOGIN_PAGE), None);
+	}
+
+	#[test]
+	fn missing_lt() {
+		let page = LOGIN_PAGE.replace(r#"name="lt""#, r#"name="loginTicket""#);
+		assert_eq!(error(&page), "login form not found");
+	}
+
+	#[test]
+	fn missing_execution() {
+		let page = LOGIN_PAGE.replace(r#"<input type="hidden" name="execution" value="e1s1" />"#, "");
+		assert_eq!(error(&page), "missing execution");
+		let page = page.replace(r#"name="_eventId""#, r#"name="eventId""#);
+		assert_eq!(error(&page), "missing execution, _eventId");
+	}
+
+	#[test]
+	fn reordered_form() {
+		// The ticket fields ahead of the credentials, the action relative to
+		// the page and the form after another one holding hidden fields.
+		let page = r#"<html><body>
+<form id="langForm" action="/cas/login"><input type="hidden" name="locale" value="en" /></form>
+<form method="post" action="login?service=y" id="loginForm">
+<input value="submit" name="_eventId" type="hidden" />
+<input name="execution" type="HIDDEN" value="e2s1" />
+<input value="LT-1-cas" type="hidden" name="lt" id="lt" />
+<input id="code" name="code" />
+<input type="hidden" name="ul" />
+<input type="password" name="password" />
+<input type="text" name="username" />
+</form>
+</body></html>"#;
+		let form = LoginForm::parse(page, &url()).unwrap();
+		assert_eq!(form.action, "https://pass.hust.edu.cn/cas/login?service=y");
+		assert_eq!(form.hidden, [
+			("_eventId".to_string(), "submit".to_string()),
+			("execution".to_string(), "e2s1".to_string()),
+			("lt".to_string(), "LT-1-cas".to_string()),
+			("ul".to_string(), String::new()),
+		]);
+		assert_eq!(form.visible, ["code", "password", "username"]);
+		assert_eq!(form.get("locale"), None);
+	}
+
+	#[test]
+	fn fields_keep_page_order() {
+		let form = LoginForm::parse(LOGIN_PAGE, &url()).unwrap();
+		let fields = form.fields(&[("ul", "U".to_string()), ("code", "1234".to_string())]);
+		let fields: Vec<(&str, &str)> = fields.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+		assert_eq!(fields[..3], [("rsa", ""), ("ul", "U"), ("pl", "")]);
+		assert_eq!(fields.last(), Some(&("code", "1234")));
+		assert!(fields.contains(&("lt", "LT-123456-AbCdEfGhIjKlMnOpQrStUvWxYz-cas")));
+	}
+}
@@ -1,3 +1,4 @@
 pub mod captcha;
 pub mod hust_login;
+pub mod login_form;
 pub mod ocr;
\ No newline at end of file
@@ -1,27 +1,25 @@
-use image::codecs::gif::GifDecoder;
 use actix_web::web::Bytes;
-use std::io::Cursor;
-use image::{DynamicImage, AnimationDecoder};
+use captcha_ocr::{Captcha, Recognizer};
+use captcha_ocr::template::TemplateRecognizer;
+use std::sync::Arc;
+use super::super::config::config::RecognizerKind;
 
-pub async fn decode_captcha(img: Bytes) -> Result<String, Box<dyn std::error::Error>> {
-	let g: GifDecoder<Cursor<Bytes>> = GifDecoder::new(Cursor::new(img)).map_err(|e| format!("Failed to create GifDecoder: {}", e))?;
-	let frames: Vec<_> = g.into_frames().collect::<Result<_, _>>()?;
-	let grayscale_frames: Vec<_> = frames.iter().map(|f| DynamicImage::ImageRgba8(f.buffer().clone()).into_luma8()).collect();
-	let mut img: image::ImageBuffer<image::Luma<u8>, Vec<u8>> = image::ImageBuffer::new(90, 58);
-	for w in 0..90 {
-		for h in 0..58 {
-			let mut cnt = 0;
-			for frame in &grayscale_frames {
-				let pixel = frame.get_pixel(w, h);
-				if pixel[0] != 255 {
-					cnt += 1;
-				}
-			}
-			if cnt >= 3 {
-				img.put_pixel(w, h, image::Luma([255u8]));
-			}
-		}
+/// The recognizer `login.recognizer` asks for. Asking for Tesseract from a
+/// binary built without the `tesseract` feature is a configuration error.
+pub fn recognizer(kind: RecognizerKind) -> Result<Arc<dyn Recognizer>, std::io::Error> {
+	match kind {
+		RecognizerKind::Native => {
+			eprintln!("The native captcha recognizer is experimental, its templates were not trained on CAS captchas");
+			Ok(Arc::new(TemplateRecognizer::default()))
+		},
+		#[cfg(feature = "tesseract")]
+		RecognizerKind::Tesseract => Ok(Arc::new(captcha_ocr::tesseract::TesseractRecognizer)),
+		#[cfg(not(feature = "tesseract"))]
+		RecognizerKind::Tesseract => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+			"login.recognizer is \"tesseract\" but this binary was built without the tesseract feature, rebuild with it or set \"native\"")),
 	}
-	let text = tesseract::ocr_from_frame(img.into_raw().as_slice(), 90, 58, 1, 90, "eng")?;
-	Ok(text)
-}
\ No newline at end of file
+}
+
+pub async fn decode_captcha(img: Bytes, recognizer: &dyn Recognizer) -> Result<Captcha, Box<dyn std::error::Error>> {
+	captcha_ocr::decode(&img, recognizer)
+}
@@ -0,0 +1,72 @@
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ledger_core::ecard::{self, EcardError};
+
+/// Time a card system session is assumed to survive without being used.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+struct Entry {
+    castgc: String,
+    client: Client,
+    last_used: Instant,
+}
+
+/// Card system clients by account, kept across jobs so that CAS is only asked
+/// for a new JSESSIONID once the card system dropped the previous one.
+#[derive(Clone, Default)]
+pub struct CardSessions(Arc<Mutex<HashMap<String, Entry>>>);
+
+impl CardSessions {
+    pub fn get<'a>(&'a self, account: &'a str, castgc: &'a str) -> CardSession<'a> {
+        CardSession { sessions: self, account, castgc }
+    }
+
+    /// The cached client of the account, or a new one when there is none for
+    /// this CASTGC or it has been idle for too long.
+    async fn client(&self, account: &str, castgc: &str) -> Result<Client, EcardError> {
+        {
+            let mut entries = self.0.lock().unwrap();
+            entries.retain(|_, entry| entry.last_used.elapsed() < IDLE_TIMEOUT);
+            if let Some(entry) = entries.get_mut(account).filter(|entry| entry.castgc == castgc) {
+                entry.last_used = Instant::now();
+                return Ok(entry.client.clone());
+            }
+        }
+        let client = ecard::get_client(castgc).await?;
+        self.0.lock().unwrap().insert(account.to_string(), Entry {
+            castgc: castgc.to_string(),
+            client: client.clone(),
+            last_used: Instant::now(),
+        });
+        Ok(client)
+    }
+
+    fn forget(&self, account: &str) {
+        self.0.lock().unwrap().remove(account);
+    }
+}
+
+/// The card system session of one account, for the duration of a job.
+pub struct CardSession<'a> {
+    sessions: &'a CardSessions,
+    account: &'a str,
+    castgc: &'a str,
+}
+
+impl CardSession<'_> {
+    /// `ecard::select` with the cached session, going through CAS again once
+    /// if the card system no longer knows it.
+    pub async fn select(&self, date_status: &str, page: u32) -> Result<serde_json::Value, EcardError> {
+        let client = self.sessions.client(self.account, self.castgc).await?;
+        match ecard::select(&client, self.account, date_status, page).await {
+            Err(EcardError::CardSessionExpired) => {
+                self.sessions.forget(self.account);
+                let client = self.sessions.client(self.account, self.castgc).await?;
+                ecard::select(&client, self.account, date_status, page).await
+            },
+            res => res,
+        }
+    }
+}
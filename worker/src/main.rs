@@ -1,16 +1,31 @@
-use chrono::{Datelike as _, Utc};
-use redis::Commands;
+use chrono::{Datelike as _, NaiveDate, Utc};
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
 use mongodb::{bson::doc, Client as MongoClient, Collection};
 use redis::Client as RedisClient;
-use serde::{Serialize, Deserialize};
+use serde::Deserialize;
 use std::time::Duration;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 use std::collections;
-use reqwest::{Client, header};
 use async_recursion::async_recursion;
+use ledger_core::classifier::{Classifier, ClassifierError};
+use ledger_core::ecard::EcardError;
+use ledger_core::keys;
+use ledger_core::model::category::{self, Categories, CategoryError};
+use ledger_core::model::job::{ErrorCode, Job};
+use ledger_core::model::report::{Meal, ReportData, Spending, Trend, Expense, Trans};
+use ledger_core::model::transaction::Transaction;
+use ledger_core::store::transactions::StoreError;
+use ledger_core::vault::VaultError;
+use queue::status::Progress;
+use card::session::{CardSession, CardSessions};
 use std::fs;
 
+pub mod card;
 pub mod config;
-pub mod utils;
+pub mod store;
+pub mod queue;
 
 #[derive(Debug, thiserror::Error)]
 pub enum WorkerError {
@@ -18,8 +33,22 @@ pub enum WorkerError {
     InvalidPeriod(String),
     #[error("Card system error: {0}")]
     CardSystemError(String),
+    #[error("Session expired: {0}")]
+    SessionExpired(String),
+    #[error("Unreadable credential: {0}")]
+    Credential(#[from] VaultError),
+    #[error("Unexpected card system response: {0}")]
+    UpstreamParse(String),
+    #[error("Merchant rules error: {0}")]
+    Rules(#[from] ClassifierError),
+    #[error("Categories error: {0}")]
+    Categories(#[from] CategoryError),
     #[error("File error: {0}")]
     FileError(String),
+    #[error("Job timed out after {0} seconds")]
+    Timeout(u64),
+    #[error("Job panicked: {0}")]
+    Panicked(String),
     #[error("Database error: {0}")]
     DatabaseError(#[from] mongodb::error::Error),
     #[error("Redis error: {0}")]
@@ -34,6 +63,53 @@ pub enum WorkerError {
     SerdeJsonError(#[from] serde_json::Error),
 }
 
+impl WorkerError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            WorkerError::InvalidPeriod(_) => ErrorCode::InvalidPeriod,
+            WorkerError::CardSystemError(_) => ErrorCode::CardSystemError,
+            WorkerError::SessionExpired(_)
+            | WorkerError::Credential(_) => ErrorCode::SessionExpired,
+            WorkerError::UpstreamParse(_)
+            | WorkerError::ParseIntError(_)
+            | WorkerError::ParseFloatError(_)
+            | WorkerError::SerdeJsonError(_) => ErrorCode::UpstreamParse,
+            WorkerError::ReqwestError(_)
+            | WorkerError::Timeout(_) => ErrorCode::UpstreamUnavailable,
+            WorkerError::FileError(_)
+            | WorkerError::Panicked(_)
+            | WorkerError::Rules(_)
+            | WorkerError::Categories(_)
+            | WorkerError::DatabaseError(_)
+            | WorkerError::RedisError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<StoreError> for WorkerError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Database(e) => WorkerError::DatabaseError(e),
+            StoreError::Ecard(e) => WorkerError::from(e),
+            StoreError::Parse(msg) => WorkerError::UpstreamParse(msg),
+        }
+    }
+}
+
+impl From<EcardError> for WorkerError {
+    fn from(e: EcardError) -> Self {
+        match e {
+            EcardError::SessionExpired => WorkerError::SessionExpired(e.to_string()),
+            // Only the card system session was dropped, the CASTGC still
+            // gets a new one on the next attempt.
+            EcardError::CardSessionExpired => WorkerError::CardSystemError(e.to_string()),
+            EcardError::CardSystem(msg) => WorkerError::CardSystemError(msg),
+            EcardError::Parse(msg) => WorkerError::UpstreamParse(msg),
+            EcardError::Http(e) => WorkerError::ReqwestError(e),
+        }
+    }
+}
+
 const REFRESH_INTERVAL: u64 = 5;
 const MEAL_TIME_RANGES: [(i64, i64, usize); 4] = [
     (60000, 90000, 0),   // breakfast
@@ -41,74 +117,45 @@ const MEAL_TIME_RANGES: [(i64, i64, usize); 4] = [
     (170000, 200000, 2), // dinner
     (220000, 240000, 3), // midnight snack
 ];
-const CAF_NAME: [&str; 14] = ["百惠", "百景", "集锦", "东一", "东二", "东三", "学一", "学二", "喻���", "食堂", "紫荆园", "西一", "西二", "东园"];
-const GRO_NAME: [&str; 2] = ["超市", "商店"];
 
+#[derive(Clone)]
 struct RedisConnections {
-    main: redis::Connection,
-    tag: redis::Connection,
-    untagged: redis::Connection,
+    main: ConnectionManager,
+    tag: ConnectionManager,
+    untagged: ConnectionManager,
 }
 
 impl RedisConnections {
-    fn new(config: &config::config::Config) -> Result<Self, redis::RedisError> {
+    async fn new(config: &config::config::Config) -> Result<Self, redis::RedisError> {
         Ok(Self {
-            main: RedisClient::open(config.redis.url.as_str())?.get_connection()?,
-            tag: RedisClient::open(config.tags_db.url.as_str())?.get_connection()?,
-            untagged: RedisClient::open(config.tags_db.url.as_str())?.get_connection()?,
+            main: RedisClient::open(config.redis.url.as_str())?.get_connection_manager().await?,
+            tag: RedisClient::open(config.tags_db.url.as_str())?.get_connection_manager().await?,
+            untagged: RedisClient::open(config.untagged_db.url.as_str())?.get_connection_manager().await?,
         })
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Expense {
-    time: String,
-    location: String,
-    amount: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-struct Trend {
-    count: i32,
-    expense: f64,
+/// What processing a job reads and writes besides the card system: the local
+/// store, the tag databases, and the job status.
+struct Context<'a> {
+    db: &'a MongoClient,
+    tag_db: ConnectionManager,
+    untagged_db: ConnectionManager,
+    progress: &'a Progress,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Meal {
-    count: i32,
-    amount: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Trans{
-    location: String,
-    amount: f64,
-    count: i32,
-}
+/// One lock per account, so that jobs of the same account never hit the card
+/// system at the same time. A job whose account is busy is deferred rather
+/// than waited for, so that it does not hold a permit other accounts could use.
+#[derive(Clone, Default)]
+struct AccountLocks(Arc<std::sync::Mutex<collections::HashMap<String, Arc<tokio::sync::Mutex<()>>>>>);
 
-
-#[derive(Serialize, Deserialize, Debug)]
-struct ReportData {
-    date: String,
-    balance: f64,
-    total_expense: f64,
-    total_topup: f64,
-    total_count: i32,
-    top_expense: Expense,
-    top_count: Trans,
-    trend: [Trend; 3],
-    cafeteria_count: i32,
-    cafeteria_amount: f64,
-    groceries_count: i32,
-    groceries_amount: f64,
-    logistics_count: i32,
-    logistics_amount: f64,
-    other_count: i32,
-    other_amount: f64,
-    breakfast: Meal,
-    lunch: Meal,
-    dinner: Meal,
-    midnight_snack: Meal,
+impl AccountLocks {
+    fn get(&self, account: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.0.lock().unwrap();
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(account.to_string()).or_default().clone()
+    }
 }
 
 #[derive(Deserialize)]
@@ -117,211 +164,365 @@ struct MerchantTag {
     tag: String,
 }
 
-fn init_tags(redis_conn: &mut redis::Connection) -> Result<(), WorkerError> {
+async fn init_tags(redis_conn: &mut ConnectionManager) -> Result<(), WorkerError> {
     let tags_content = fs::read_to_string("tags.json")
         .map_err(|e| WorkerError::FileError(e.to_string()))?;
     
     let merchant_tags: Vec<MerchantTag> = serde_json::from_str(&tags_content)
         .map_err(|e| WorkerError::FileError(e.to_string()))?;
 
+    // Only merchants without a tag are seeded, tags changed through the API
+    // are kept across restarts.
     for tag in merchant_tags {
-        let _: () = redis_conn.set(tag.mercacc.to_string(), tag.tag)?;
+        let _: () = redis_conn.set_nx(tag.mercacc.to_string(), tag.tag).await?;
     }
 
     Ok(())
 }
 
+/// Seeds the category tree from `categories.json` unless one is set already.
+async fn init_categories(redis_conn: &mut ConnectionManager) -> Result<(), WorkerError> {
+    let categories = fs::read_to_string("categories.json")
+        .map_err(|e| WorkerError::FileError(e.to_string()))?;
+    Categories::from_json(&categories)?;
+    let _: () = redis_conn.set_nx(keys::CATEGORIES, categories).await?;
+    Ok(())
+}
+
+/// The category tree in the tags database, the top-level categories alone if
+/// it was never set.
+async fn load_categories(tag_db: &mut ConnectionManager) -> Result<Categories, WorkerError> {
+    match tag_db.get::<_, Option<String>>(keys::CATEGORIES).await? {
+        Some(categories) => Ok(Categories::from_json(&categories)?),
+        None => Ok(Categories::default()),
+    }
+}
+
+/// Seeds the merchant rules from `rules.json` unless some are set already, so
+/// that rules changed in Redis are kept across restarts.
+async fn init_rules(redis_conn: &mut ConnectionManager) -> Result<(), WorkerError> {
+    let rules = fs::read_to_string("rules.json")
+        .map_err(|e| WorkerError::FileError(e.to_string()))?;
+    Classifier::from_json(&rules)?;
+    let _: () = redis_conn.set_nx(keys::RULES, rules).await?;
+    Ok(())
+}
+
+/// The merchant rules in the tags database, none if they were never set.
+async fn load_rules(tag_db: &mut ConnectionManager) -> Result<Classifier, WorkerError> {
+    match tag_db.get::<_, Option<String>>(keys::RULES).await? {
+        Some(rules) => Ok(Classifier::from_json(&rules)?),
+        None => Ok(Classifier::default()),
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let config = config::config::init_config().await;
+    ledger_core::upstream::init(config.upstream.clone());
+    ledger_core::vault::init(&config.credentials);
     let mongo_client = MongoClient::with_uri_str(&config.db.url).await.unwrap();
-    let mut redis_conns = RedisConnections::new(&config).unwrap();
+    let mut redis_conns = RedisConnections::new(&config).await.unwrap();
     
     // Initialize tags from JSON file
-    if let Err(e) = init_tags(&mut redis_conns.tag) {
+    if let Err(e) = init_tags(&mut redis_conns.tag).await {
         eprintln!("Failed to initialize tags: {}", e);
     }
+    if let Err(e) = init_categories(&mut redis_conns.tag).await {
+        eprintln!("Failed to initialize categories: {}", e);
+    }
+    if let Err(e) = init_rules(&mut redis_conns.tag).await {
+        eprintln!("Failed to initialize merchant rules: {}", e);
+    }
 
+    // Claims block the connection they are sent on, so they get their own.
+    let mut claim_conn = RedisClient::open(config.redis.url.as_str()).unwrap()
+        .get_multiplexed_async_connection().await.unwrap();
+    let queue = Arc::new(queue::job::Queue::new(config.queue.clone()));
+    let permits = Arc::new(Semaphore::new(config.queue.concurrency));
+    let accounts = AccountLocks::default();
+    let card_sessions = CardSessions::default();
     loop {
-        process_queue(&mongo_client, &mut redis_conns).await;
-        tokio::time::sleep(Duration::from_secs(REFRESH_INTERVAL)).await;
+        process_queue(&mongo_client, &mut redis_conns, &mut claim_conn, &queue, &permits, &accounts, &card_sessions).await;
     }
 }
 
-async fn process_queue(mongo_client: &MongoClient, redis_conns: &mut RedisConnections) {
-    let queue: Vec<String> = redis_conns.main.keys("request:*").unwrap();
-    for key in queue {
+async fn process_queue(mongo_client: &MongoClient, redis_conns: &mut RedisConnections, claim_conn: &mut redis::aio::MultiplexedConnection,
+queue: &Arc<queue::job::Queue>, permits: &Arc<Semaphore>, accounts: &AccountLocks, card_sessions: &CardSessions) {
+    let maintenance = async {
+        queue.heartbeat(&mut redis_conns.main).await?;
+        queue.reclaim(&mut redis_conns.main).await?;
+        queue.promote_delayed(&mut redis_conns.main).await
+    };
+    if let Err(e) = maintenance.await {
+        eprintln!("Failed to maintain the job queue: {}", e);
+    }
+    let permit = match tokio::time::timeout(Duration::from_secs(REFRESH_INTERVAL), permits.clone().acquire_owned()).await {
+        Ok(Ok(permit)) => permit,
+        _ => return,
+    };
+    let claimed = match queue.claim(claim_conn, REFRESH_INTERVAL as f64).await {
+        Ok(Some(claimed)) => claimed,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Failed to claim a job: {}", e);
+            tokio::time::sleep(Duration::from_secs(REFRESH_INTERVAL)).await;
+            return;
+        }
+    };
+    let account = match accounts.get(&claimed.job.account).try_lock_owned() {
+        Ok(account) => account,
+        Err(_) => {
+            if let Err(e) = queue.defer(&mut redis_conns.main, claimed, REFRESH_INTERVAL).await {
+                eprintln!("Failed to defer a job: {}", e);
+            }
+            return;
+        }
+    };
+
+    let mongo_client = mongo_client.clone();
+    let mut redis_conns = redis_conns.clone();
+    let queue = queue.clone();
+    let card_sessions = card_sessions.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        let _account = account;
+        let job = &claimed.job;
+        let key = format!("{}:{}:{}", job.id, job.account, job.period);
         println!("Processing: {}", key);
-        let castgc: String = redis_conns.main.get(&key).unwrap();
-        let castgc: &str = castgc.split(":").collect::<Vec<&str>>()[1];
-        let t = key.split(":").collect::<Vec<&str>>();
-        let period = t[2];
-        let account = t[1];
-        let key_res = format!("result:{}:{}", account, period);
-        let _: () = redis_conns.main.del(&key).unwrap();
-        let _:() = match process(castgc, period, account.to_string(), &mongo_client, &mut redis_conns.tag, None, &mut redis_conns.untagged).await{
-            Ok(id) => {
-                redis_conns.main.set(key_res, id).unwrap()
+        // Run apart, so that a panic or a job past its deadline is failed
+        // instead of holding its permit and request key.
+        let mut running = tokio::spawn(run(job.clone(), mongo_client, redis_conns.clone(), card_sessions));
+        let processed = match tokio::time::timeout(queue.job_timeout(), &mut running).await {
+            Ok(Ok(processed)) => processed,
+            Ok(Err(e)) => Err(WorkerError::Panicked(e.to_string())),
+            Err(_) => {
+                running.abort();
+                Err(WorkerError::Timeout(queue.job_timeout().as_secs()))
             },
+        };
+        let res = match processed {
+            Ok(id) => queue.complete(&mut redis_conns.main, claimed, id).await,
             Err(e) => {
-                redis_conns.main.set(key_res, format!("error: {}", e)).unwrap()
+                eprintln!("Failed: {}: {}", key, e);
+                queue.fail(&mut redis_conns.main, claimed, &e).await
             }
         };
+        if let Err(e) = res {
+            eprintln!("Failed to acknowledge {}: {}", key, e);
+        }
         println!("Done: {}", key);
-    }
+    });
 }
 
+/// Processes a claimed job, returning the path of its report.
+async fn run(job: Job, mongo_client: MongoClient, redis_conns: RedisConnections, card_sessions: CardSessions) -> Result<String, WorkerError> {
+    let progress = Progress::new(redis_conns.main.clone(), &job.id);
+    progress.started(job.attempts + 1).await;
+    let castgc = job.castgc()?;
+    let mut cx = Context {
+        db: &mongo_client,
+        tag_db: redis_conns.tag,
+        untagged_db: redis_conns.untagged,
+        progress: &progress,
+    };
+    process(&card_sessions.get(&job.account, &castgc), &job.period, job.account.clone(), &mut cx, None).await
+}
 
-#[async_recursion]
-async fn process(castgc: &str, period: &str, account: String, 
-db: &MongoClient, tag_db: &mut redis::Connection, recursion: Option<chrono::DateTime<Utc>>, 
-untagged_db: &mut redis::Connection) 
--> Result<String, WorkerError> {
-    let cookie_store = reqwest::cookie::Jar::default();
-    let jsession = utils::hust_login::get_jsession(castgc).await.unwrap();
-	let url = reqwest::Url::parse("http://ecard.m.hust.edu.cn").unwrap();
-	cookie_store.add_cookie_str(("JSESSIONID=".to_owned()+&jsession).as_str(), &url);
-    let client = Client::builder()
-		.cookie_provider(cookie_store.into())
-		.default_headers({
-			let mut headers = header::HeaderMap::new();
-			headers.insert(header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3".parse().unwrap());
-			headers.insert(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8".parse().unwrap());
-			headers.insert(header::ACCEPT_ENCODING, "gzip, deflate, sdch".parse().unwrap());
-			headers.insert(header::ACCEPT_LANGUAGE, "zh-CN,zh;q=0.8".parse().unwrap());
-			headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
-			headers
-		})
-		.build()
-		.unwrap();
-    let mut form: collections::HashMap<&str, String> = collections::HashMap::new();
-    form.insert("account", account.clone());
-    form.insert("curpage", "1".to_string());
-    form.insert("typeStatus", "1".to_string());
-    let date = chrono::Utc::now();
-    let coll: Collection<ReportData> = match period {
-        "week" => {
-            form.insert("dateStatus", "3".to_string());
-            db.database("report_week").collection(account.as_str())
+struct Summary {
+    trans: collections::HashMap<String, (i32, f64)>,
+    meals: [Meal; 4],
+    balance: f64,
+    total_expense: f64,
+    total_topup: f64,
+    total_count: i32,
+    top_expense: Expense,
+    top_count: Trans,
+    cafeteria_count: i32,
+    cafeteria_amount: f64,
+    groceries_count: i32,
+    groceries_amount: f64,
+    logistics_count: i32,
+    logistics_amount: f64,
+    other_count: i32,
+    other_amount: f64,
+    categories: Vec<Spending>,
+}
+
+impl Summary {
+    fn new() -> Self {
+        Self {
+            trans: collections::HashMap::new(),
+            meals: core::array::from_fn(|_| Meal { count: 0, amount: 0.0 }),
+            balance: -1.0,
+            total_expense: 0.0,
+            total_topup: 0.0,
+            total_count: 0,
+            top_expense: Expense {
+                time: "1".to_string(),
+                location: "1".to_string(),
+                amount: 0.0,
+            },
+            top_count: Trans {
+                location: "1".to_string(),
+                amount: 0.0,
+                count: 0,
+            },
+            cafeteria_count: 0,
+            cafeteria_amount: 0.0,
+            groceries_count: 0,
+            groceries_amount: 0.0,
+            logistics_count: 0,
+            logistics_amount: 0.0,
+            other_count: 0,
+            other_amount: 0.0,
+            categories: Vec::new(),
         }
-        "month" => {
-            let date_status = match recursion.clone() {
-                Some(t) => t.format("%Y-%m-01").to_string(),
-                None => date.format("%Y-%m-01").to_string()
-            };
-            form.insert("dateStatus", date_status);
-            db.database("report_month").collection(account.as_str())
-        },
-        _ => return Err(WorkerError::InvalidPeriod(period.to_string()))
-    };
-    let api = "http://ecard.m.hust.edu.cn/wechat-web/QueryController/select.html";
-    let mut trans: collections::HashMap<String,(i32,f64)> = collections::HashMap::new();
-    let mut meals: [Meal; 4] = core::array::from_fn(|_| Meal { count: 0, amount: 0.0 });
-    let mut balance: f64 = -1.0;
-    let mut total_expense: f64 = 0.0;
-    let mut total_topup: f64 = 0.0;
-    let mut total_count: i32 = 0;
-    let mut top_expense: Expense = Expense {
-        time: "1".to_string(),
-        location: "1".to_string(),
-        amount: 0.0,
-    };
-    let mut top_count: Trans = Trans {
-        location: "1".to_string(),
-        amount: 0.0,
-        count: 0,
-    };
-    let mut cafeteria_count: i32 = 0;
-    let mut cafeteria_amount: f64 = 0.0;
-    let mut groceries_count: i32 = 0;
-    let mut groceries_amount: f64 = 0.0;
-    let mut logistics_count: i32 = 0;
-    let mut logistics_amount: f64 = 0.0;
-    let mut other_count: i32 = 0;
-    let mut other_amount: f64 = 0.0;
-
-    loop{
-        let res = client.get(api).query(&form).send().await?;
-        let text = &res.text().await?;
-        let text = &text[9..text.len()-1];
-        let data: serde_json::Value = serde_json::from_str(&text)?;
-        if balance == -1.0 {
-            balance = data["total"][0]["cardbal"].as_str().unwrap().parse::<f64>()? / 100.0;
+    }
+
+    async fn add(&mut self, t: &Transaction, classifier: &Classifier, categories: &Categories, cx: &mut Context<'_>) -> Result<(), WorkerError> {
+        if self.balance == -1.0 {
+            self.balance = t.balance;
         }
-        if data["retcode"].as_str().unwrap() != "0" {
-            return Err(WorkerError::CardSystemError(data["errmsg"].as_str().unwrap().to_string()));
+        if t.topup {
+            self.total_topup += t.amount;
+            return Ok(());
         }
-        for item in data["total"].as_array().unwrap().to_vec() {
-            if item["sign_tranamt"].as_str().unwrap().parse::<i64>()? > 0 {
-                total_topup += item["tranamt"].as_str().unwrap().parse::<f64>()? / 100.0;
-                continue;
-            }
-            let mut occtime = item["occtime"].as_str().unwrap().parse::<i64>()?;
-            let mercname = item["mercname"].as_str().unwrap().to_string();
-            let mercacc = item["mercacc"].as_str().unwrap();
-            let mut tranamt = item["tranamt"].as_str().unwrap().parse::<f64>()?;
-            tranamt /= 100.0;
-            total_expense += tranamt;
-            total_count += 1;
-            if trans.contains_key(&mercname) {
-                let t = trans.get_mut(mercname.as_str()).unwrap();
-                t.0 += 1;
-                t.1 += tranamt;
-                if t.0 > top_count.count {
-                    top_count = Trans {
-                        location: mercname.clone(),
-                        amount: t.1,
-                        count: t.0,
-                    };
-                }
-            }else{
-                trans.insert(mercname.clone(), (1, tranamt));
-            }
-
-            if tranamt > top_expense.amount {
-                top_expense = Expense {
-                    time: item["occtime"].as_str().unwrap().to_string(),
+        let mut occtime = t.occtime;
+        let mercname = t.mercname.clone();
+        let mercacc = t.mercacc.as_str();
+        let tranamt = t.amount;
+        self.total_expense += tranamt;
+        self.total_count += 1;
+        if self.trans.contains_key(&mercname) {
+            let t = self.trans.get_mut(mercname.as_str()).unwrap();
+            t.0 += 1;
+            t.1 += tranamt;
+            if t.0 > self.top_count.count {
+                self.top_count = Trans {
                     location: mercname.clone(),
-                    amount: tranamt,
+                    amount: t.1,
+                    count: t.0,
                 };
             }
+        }else{
+            self.trans.insert(mercname.clone(), (1, tranamt));
+        }
 
-            let tag: String = tag_db.get(mercacc).unwrap_or(process_untagged(untagged_db, tag_db, &mercacc.to_string(), &mercname));
-            match tag.as_str() {
-                "CAF" => {
-                    occtime %= 1000000;
-                    if let Some((_, _, idx)) = MEAL_TIME_RANGES.iter()
-                        .find(|(start, end, _)| occtime >= *start && occtime <= *end) {
-                        meals[*idx].count += 1;
-                        meals[*idx].amount += tranamt;
-                    }
-                    cafeteria_amount += tranamt;
-                    cafeteria_count += 1;
-                },
-                "GRO" => {
-                    groceries_count += 1;
-                    groceries_amount += tranamt;
-                },
-                "LOG" => {
-                    logistics_count += 1;
-                    logistics_amount += tranamt;
-                },
-                "OTH" => {
-                    other_count += 1;
-                    other_amount += tranamt;
-                }
-                _ => {}
+        if tranamt > self.top_expense.amount {
+            self.top_expense = Expense {
+                time: t.occtime.to_string(),
+                location: mercname.clone(),
+                amount: tranamt,
             };
         }
-        form.remove("curpage");
-        form.insert("curpage", data["nextpage"].as_str().unwrap().to_string());
-        if data["nextpage"].as_str().unwrap() == "0" {
-            break;
+
+        let tag: String = match cx.tag_db.get::<_, Option<String>>(mercacc).await? {
+            Some(tag) => tag,
+            None => process_untagged(classifier, cx, t).await?,
+        };
+        Spending::record(&mut self.categories, categories, &tag, tranamt);
+        match category::top(&tag) {
+            "CAF" => {
+                occtime %= 1000000;
+                if let Some((_, _, idx)) = MEAL_TIME_RANGES.iter()
+                    .find(|(start, end, _)| occtime >= *start && occtime <= *end) {
+                    self.meals[*idx].count += 1;
+                    self.meals[*idx].amount += tranamt;
+                }
+                self.cafeteria_amount += tranamt;
+                self.cafeteria_count += 1;
+            },
+            "GRO" => {
+                self.groceries_count += 1;
+                self.groceries_amount += tranamt;
+            },
+            "LOG" => {
+                self.logistics_count += 1;
+                self.logistics_amount += tranamt;
+            },
+            "OTH" => {
+                self.other_count += 1;
+                self.other_amount += tranamt;
+            }
+            _ => {}
+        };
+        Ok(())
+    }
+
+    fn into_report(self, date: String, trend: Vec<Trend>) -> ReportData {
+        ReportData {
+            date,
+            balance: self.balance,
+            total_expense: self.total_expense,
+            total_topup: self.total_topup,
+            total_count: self.total_count,
+            top_expense: self.top_expense,
+            top_count: self.top_count,
+            trend,
+            cafeteria_count: self.cafeteria_count,
+            cafeteria_amount: self.cafeteria_amount,
+            groceries_count: self.groceries_count,
+            groceries_amount: self.groceries_amount,
+            logistics_count: self.logistics_count,
+            logistics_amount: self.logistics_amount,
+            other_count: self.other_count,
+            other_amount: self.other_amount,
+            breakfast: self.meals[0].clone(),
+            lunch: self.meals[1].clone(),
+            dinner: self.meals[2].clone(),
+            midnight_snack: self.meals[3].clone(),
+            categories: self.categories,
+        }
+    }
+}
+
+/// Aggregates the stored transactions of `from..=to` into a summary.
+async fn summarize(cx: &mut Context<'_>, account: &str, from: NaiveDate, to: NaiveDate)
+-> Result<Summary, WorkerError> {
+    let (lower, upper) = store::transactions::bounds(from, to);
+    let categories = load_categories(&mut cx.tag_db).await?;
+    let classifier = load_rules(&mut cx.tag_db).await?;
+    let mut summary = Summary::new();
+    for t in store::transactions::find(cx.db, account, lower, upper).await? {
+        summary.add(&t, &classifier, &categories, cx).await?;
+    }
+    if summary.balance == -1.0 {
+        if let Some(balance) = store::transactions::balance_at(cx.db, account, upper).await? {
+            summary.balance = balance;
         }
+    }
+    Ok(summary)
+}
+
+#[async_recursion]
+async fn process(card: &CardSession<'_>, period: &str, account: String, 
+cx: &mut Context<'_>, recursion: Option<chrono::DateTime<Utc>>)
+-> Result<String, WorkerError> {
+    if period.starts_with("range_") {
+        return process_range(card, period, account, cx).await;
+    }
+    if period == "semester" || period == "year" {
+        return process_term(card, period, account, cx).await;
+    }
+    let date = chrono::Utc::now();
+    let today = chrono::Local::now().date_naive();
+    let (coll, from, to): (Collection<ReportData>, NaiveDate, NaiveDate) = match period {
+        "week" => (cx.db.database("report_week").collection(account.as_str()), today - chrono::Duration::days(6), today),
+        "month" => {
+            let month_start = match recursion {
+                Some(t) => t.date_naive().with_day(1).unwrap(),
+                None => today.with_day(1).unwrap()
+            };
+            let month_end = (month_start + chrono::Duration::days(31)).with_day(1).unwrap() - chrono::Duration::days(1);
+            (cx.db.database("report_month").collection(account.as_str()), month_start, month_end)
+        },
+        _ => return Err(WorkerError::InvalidPeriod(period.to_string()))
     };
+    store::transactions::sync(card, &account, from, to, cx.db, cx.progress).await?;
+    let summary = summarize(cx, &account, from, to).await?;
 
-    
-    let mut trend = [Trend { count: 0, expense: 0.0 }; 3];
+    let mut trend = vec![Trend { count: 0, expense: 0.0 }; 3];
     let fmtstr = match recursion {
         Some(t) => t.format("%Y%m").to_string(),
         None => {
@@ -344,23 +545,11 @@ untagged_db: &mut redis::Connection)
                     for i in 1 as i64..=3 {
                         month_start -= chrono::Duration::days(1);
                         month_start = month_start.with_day(1).unwrap();
-                        let month_id = month_start.format("%Y%m").to_string();
-                        match coll.find_one(doc! { "date": month_id.clone() }).await.unwrap() {
-                            Some(report) => {
-                                trend[(i-1) as usize] = Trend {
-                                    count: report.total_count,
-                                    expense: report.total_expense,
-                                };
-                            },
-                            None => {
-                                process(castgc, "month", account.clone(), db, tag_db, Some(month_start), untagged_db).await?;
-                                let report = coll.find_one(doc! { "date": month_id }).await?.unwrap();
-                                trend[(i-1) as usize] = Trend {
-                                    count: report.total_count,
-                                    expense: report.total_expense,
-                                };
-                            }
-                        }
+                        let report = month_report(card, &account, month_start.date_naive(), false, cx).await?;
+                        trend[(i-1) as usize] = Trend {
+                            count: report.total_count,
+                            expense: report.total_expense,
+                        };
                     };
                     date.format("%Y%m").to_string()
                 },
@@ -368,45 +557,193 @@ untagged_db: &mut redis::Connection)
             }
         }
     };
-    let result = ReportData {
-        date: fmtstr,
-        balance,
-        total_expense,
-        total_topup,
-        total_count,
-        top_expense,
-        top_count,
-        trend,
-        cafeteria_count,
-        cafeteria_amount,
-        groceries_count,
-        groceries_amount,
-        logistics_count,
-        logistics_amount,
-        other_count,
-        other_amount,
-        breakfast: meals[0].clone(),
-        lunch: meals[1].clone(),
-        dinner: meals[2].clone(),
-        midnight_snack: meals[3].clone(),
-    };
+    let result = summary.into_report(fmtstr, trend);
     let id = coll.insert_one(result).await.unwrap().inserted_id.as_object_id().unwrap().to_hex();
     Ok(format!("report_{}/{}/{}", period, account, id))
 }
 
-fn process_untagged(untagged_db: &mut redis::Connection, tags_db: &mut redis::Connection, mercacc: &String, mercname: &String) -> String{
-    for i in CAF_NAME.iter() {
-        if mercname.contains(i) {
-            let _:() = tags_db.set(mercacc, "CAF").unwrap();
-            return "CAF".to_string();
-        }
+/// Parses a `range_<from>_<to>` period (both dates as `%Y%m%d`) into its bounds.
+fn parse_range(period: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (from, to) = period.strip_prefix("range_")?.split_once('_')?;
+    let from = NaiveDate::parse_from_str(from, "%Y%m%d").ok()?;
+    let to = NaiveDate::parse_from_str(to, "%Y%m%d").ok()?;
+    if from > to {
+        return None;
+    }
+    Some((from, to))
+}
+
+async fn process_range(card: &CardSession<'_>, period: &str, account: String,
+cx: &mut Context<'_>)
+-> Result<String, WorkerError> {
+    let (from, to) = parse_range(period).ok_or_else(|| WorkerError::InvalidPeriod(period.to_string()))?;
+    let coll: Collection<ReportData> = cx.db.database("report_range").collection(account.as_str());
+    store::transactions::sync(card, &account, from, to, cx.db, cx.progress).await?;
+    let summary = summarize(cx, &account, from, to).await?;
+
+    let date = format!("{}-{}", from.format("%Y%m%d"), to.format("%Y%m%d"));
+    let result = summary.into_report(date, Vec::new());
+    let id = coll.insert_one(result).await?.inserted_id.as_object_id().unwrap().to_hex();
+    Ok(format!("report_range/{}/{}", account, id))
+}
+
+/// Returns the first day of every month of the current semester or academic
+/// year up to and including the current month, together with the report date.
+/// The academic year starts in September; the first semester runs from
+/// September to January and the second one from February to August.
+fn term_months(period: &str, today: NaiveDate) -> Option<(String, Vec<NaiveDate>)> {
+    let year_start = if today.month() >= 9 { today.year() } else { today.year() - 1 };
+    let (date, start) = match period {
+        "year" => (year_start.to_string(), NaiveDate::from_ymd_opt(year_start, 9, 1)?),
+        "semester" => {
+            if today.month() >= 9 || today.month() == 1 {
+                (format!("{}-1", year_start), NaiveDate::from_ymd_opt(year_start, 9, 1)?)
+            } else {
+                (format!("{}-2", year_start), NaiveDate::from_ymd_opt(year_start + 1, 2, 1)?)
+            }
+        },
+        _ => return None,
     };
-    for i in GRO_NAME.iter() {
-        if mercname.contains(i) {
-            let _:() = tags_db.set(mercacc, "GRO").unwrap();
-            return "GRO".to_string();
+    let mut months = Vec::new();
+    let mut month = start;
+    while month <= today {
+        months.push(month);
+        month = (month + chrono::Duration::days(31)).with_day(1)?;
+    }
+    Some((date, months))
+}
+
+/// The newest stored report of the month starting at `month_start`, if it was
+/// generated after the month ended. Reports generated during the month miss
+/// its later transactions.
+async fn completed_month(coll: &Collection<ReportData>, month_start: NaiveDate) -> Result<Option<ReportData>, WorkerError> {
+    let next_month = (month_start + chrono::Duration::days(31)).with_day(1).unwrap();
+    let ended = next_month.and_hms_opt(0, 0, 0).unwrap()
+        .and_local_timezone(chrono::Local).earliest().unwrap().timestamp();
+    // Object ids start with their creation time, so the smallest id of that
+    // second bounds the reports generated since.
+    let mut since = [0u8; 12];
+    since[..4].copy_from_slice(&(ended as u32).to_be_bytes());
+    let report = coll.find_one(doc! {
+        "date": month_start.format("%Y%m").to_string(),
+        "_id": { "$gte": mongodb::bson::oid::ObjectId::from_bytes(since) },
+    }).sort(doc! { "_id": -1 }).await?;
+    Ok(report)
+}
+
+/// Loads the month report starting at `month_start`, generating it through the
+/// month path of `process` when it is missing, still in progress, or was
+/// generated before the month ended.
+async fn month_report(card: &CardSession<'_>, account: &str, month_start: NaiveDate, current: bool,
+cx: &mut Context<'_>)
+-> Result<ReportData, WorkerError> {
+    let coll: Collection<ReportData> = cx.db.database("report_month").collection(account);
+    if !current {
+        if let Some(report) = completed_month(&coll, month_start).await? {
+            return Ok(report);
         }
-    };
-    let _:() = untagged_db.set(mercacc, mercname).unwrap();
-    "OTH".to_string()
-}
\ No newline at end of file
+    }
+    let recursion = month_start.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let path = process(card, "month", account.to_string(), cx, Some(recursion)).await?;
+    if !current {
+        cx.progress.backfilled().await;
+    }
+    let id = path.rsplit('/').next().unwrap();
+    let report = coll.find_one(doc! { "_id": mongodb::bson::oid::ObjectId::parse_str(id).unwrap() }).await?.unwrap();
+    Ok(report)
+}
+
+async fn process_term(card: &CardSession<'_>, period: &str, account: String,
+cx: &mut Context<'_>)
+-> Result<String, WorkerError> {
+    let today = chrono::Local::now().date_naive();
+    let (date, months) = term_months(period, today).ok_or_else(|| WorkerError::InvalidPeriod(period.to_string()))?;
+    let coll: Collection<ReportData> = cx.db.database(format!("report_{}", period).as_str()).collection(account.as_str());
+
+    let mut result: Option<ReportData> = None;
+    let mut trend = Vec::with_capacity(months.len());
+    for (i, month_start) in months.iter().enumerate() {
+        let report = month_report(card, &account, *month_start, i == months.len() - 1, cx).await?;
+        trend.push(Trend {
+            count: report.total_count,
+            expense: report.total_expense,
+        });
+        result = Some(match result {
+            Some(acc) => acc.merge(report),
+            None => report,
+        });
+    }
+    let mut result = result.ok_or_else(|| WorkerError::InvalidPeriod(period.to_string()))?;
+    result.date = date;
+    result.trend = trend;
+    let id = coll.insert_one(result).await?.inserted_id.as_object_id().unwrap().to_hex();
+    Ok(format!("report_{}/{}/{}", period, account, id))
+}
+
+async fn process_untagged(classifier: &Classifier, cx: &mut Context<'_>, t: &Transaction) -> Result<String, WorkerError> {
+    let (mercacc, mercname) = (&t.mercacc, &t.mercname);
+    if let Some(tag) = classifier.classify(mercacc, mercname) {
+        let _:() = cx.tag_db.set(mercacc, tag).await?;
+        return Ok(tag.to_string());
+    }
+    // Left for review through `GET /tags/untagged`. Transactions already
+    // counted, by an earlier report over the same days, are not counted again.
+    let added: i64 = cx.untagged_db.sadd(keys::untagged_seen(mercacc), &t.id).await?;
+    if added == 0 {
+        return Ok("OTH".to_string());
+    }
+    let key = keys::untagged(mercacc);
+    let now = Utc::now().timestamp();
+    let _: () = redis::pipe()
+        .hset(&key, "mercname", mercname).ignore()
+        .hset_nx(&key, "first_seen", now).ignore()
+        .hset(&key, "last_seen", now).ignore()
+        .hincr(&key, "hits", 1).ignore()
+        .zadd(keys::UNTAGGED, mercacc, now).ignore()
+        .query_async(&mut cx.untagged_db).await?;
+    Ok("OTH".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn range_bounds() {
+        assert_eq!(parse_range("range_20260901_20261018"), Some((day(2026, 9, 1), day(2026, 10, 18))));
+        assert_eq!(parse_range("range_20261018_20261018"), Some((day(2026, 10, 18), day(2026, 10, 18))));
+        assert_eq!(parse_range("range_20261018_20260901"), None);
+        assert_eq!(parse_range("range_20260231_20261018"), None);
+        assert_eq!(parse_range("range_20260901"), None);
+        assert_eq!(parse_range("20260901_20261018"), None);
+    }
+
+    #[test]
+    fn first_semester_crosses_the_year() {
+        let (date, months) = term_months("semester", day(2027, 1, 15)).unwrap();
+        assert_eq!(date, "2026-1");
+        assert_eq!(months, vec![day(2026, 9, 1), day(2026, 10, 1), day(2026, 11, 1), day(2026, 12, 1), day(2027, 1, 1)]);
+    }
+
+    #[test]
+    fn second_semester() {
+        let (date, months) = term_months("semester", day(2027, 3, 1)).unwrap();
+        assert_eq!(date, "2026-2");
+        assert_eq!(months, vec![day(2027, 2, 1), day(2027, 3, 1)]);
+    }
+
+    #[test]
+    fn academic_year() {
+        let (date, months) = term_months("year", day(2026, 9, 1)).unwrap();
+        assert_eq!((date.as_str(), months), ("2026", vec![day(2026, 9, 1)]));
+        let (date, months) = term_months("year", day(2027, 8, 31)).unwrap();
+        assert_eq!(date, "2026");
+        assert_eq!(months.len(), 12);
+        assert_eq!(months.last(), Some(&day(2027, 8, 1)));
+        assert!(term_months("week", day(2026, 10, 18)).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use redis::{AsyncCommands, Direction};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ledger_core::config::config;
+use ledger_core::keys::{self, PENDING, DELAYED, DEAD, PROCESSING_PREFIX, CONSUMER_PREFIX};
+use ledger_core::model::job::{Job, ErrorCode, JobError, JobResult};
+use crate::WorkerError;
+use super::status;
+
+impl From<&WorkerError> for JobError {
+    fn from(e: &WorkerError) -> Self {
+        JobError::new(e.code(), e.to_string())
+    }
+}
+
+/// A job taken off the pending list, along with its serialized form as stored
+/// in the processing list.
+pub struct Claimed {
+    pub job: Job,
+    raw: String,
+}
+
+pub struct Queue {
+    consumer: String,
+    processing: String,
+    settings: config::Queue,
+}
+
+/// Deletes the request key of a job, only if it still stands for this job:
+/// once it expired, the same account and period may have a newer job.
+async fn release<C: AsyncCommands>(con: &mut C, id: &str, account: &str, period: &str) -> Result<(), WorkerError> {
+    let key = keys::request(account, period);
+    if con.get::<_, Option<String>>(&key).await?.as_deref() == Some(id) {
+        let _: () = con.del(&key).await?;
+    }
+    Ok(())
+}
+
+/// Moves a job that cannot be read to the dead-letter list, releasing its
+/// request key when enough of it can be read to find it.
+async fn drop_malformed<C: AsyncCommands>(con: &mut C, raw: &str) -> Result<(), WorkerError> {
+    let _: () = con.rpush(DEAD, raw).await?;
+    let job: serde_json::Value = match serde_json::from_str(raw) {
+        Ok(job) => job,
+        Err(_) => return Ok(()),
+    };
+    if let (Some(id), Some(account), Some(period)) = (job["id"].as_str(), job["account"].as_str(), job["period"].as_str()) {
+        release(con, id, account, period).await?;
+    }
+    Ok(())
+}
+
+/// Stores the final result of a job and releases its request key, so that the
+/// API can pick the result up.
+async fn finish<C: AsyncCommands>(con: &mut C, job: &Job, result: &JobResult) -> Result<(), WorkerError> {
+    let _: () = con.set(keys::result(&job.account, &job.period), serde_json::to_string(result)?).await?;
+    release(con, &job.id, &job.account, &job.period).await
+}
+
+impl Queue {
+    pub fn new(settings: config::Queue) -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let consumer = format!("{}-{:x}", std::process::id(), nanos);
+        Self {
+            processing: format!("{}{}", PROCESSING_PREFIX, consumer),
+            consumer,
+            settings,
+        }
+    }
+
+    /// Longest time a job may run for.
+    pub fn job_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.settings.job_timeout)
+    }
+
+    /// Marks this worker as alive for one visibility timeout. Jobs of a worker
+    /// that has not reported in for longer are handed to another one.
+    pub async fn heartbeat<C: AsyncCommands>(&self, con: &mut C) -> Result<(), WorkerError> {
+        let key = format!("{}{}", CONSUMER_PREFIX, self.consumer);
+        let _: () = con.set_ex(key, chrono::Utc::now().timestamp(), self.settings.visibility_timeout).await?;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` seconds for a pending job and moves it to this
+    /// worker's processing list. The connection is blocked in the meantime.
+    pub async fn claim<C: AsyncCommands>(&self, con: &mut C, timeout: f64) -> Result<Option<Claimed>, WorkerError> {
+        let raw: Option<String> = con.blmove(PENDING, &self.processing, Direction::Left, Direction::Right, timeout).await?;
+        let raw = match raw {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        match serde_json::from_str::<Job>(&raw) {
+            Ok(job) => Ok(Some(Claimed { job, raw })),
+            Err(e) => {
+                eprintln!("Dropping malformed job {}: {}", raw, e);
+                drop_malformed(con, &raw).await?;
+                let _: () = con.lrem(&self.processing, 1, &raw).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Acknowledges a processed job, `report` being the path of its report.
+    pub async fn complete<C: AsyncCommands>(&self, con: &mut C, claimed: Claimed, report: String) -> Result<(), WorkerError> {
+        finish(con, &claimed.job, &JobResult::Finished { report }).await?;
+        status::set_state(con, &claimed.job.id, "finished", None).await;
+        let _: () = con.lrem(&self.processing, 1, &claimed.raw).await?;
+        Ok(())
+    }
+
+    /// Puts a claimed job back for `delay` seconds without counting an
+    /// attempt, for jobs that cannot start yet.
+    pub async fn defer<C: AsyncCommands>(&self, con: &mut C, claimed: Claimed, delay: u64) -> Result<(), WorkerError> {
+        let due = chrono::Utc::now().timestamp() + delay as i64;
+        let _: () = redis::pipe().atomic()
+            .zadd(DELAYED, &claimed.raw, due).ignore()
+            .lrem(&self.processing, 1, &claimed.raw).ignore()
+            .query_async(con).await?;
+        Ok(())
+    }
+
+    /// Schedules a failed job for another attempt with exponential backoff.
+    /// Once it failed for good or ran out of attempts, it is moved to the
+    /// dead-letter list and completed with the error.
+    pub async fn fail<C: AsyncCommands>(&self, con: &mut C, claimed: Claimed, error: &WorkerError) -> Result<(), WorkerError> {
+        let mut job = claimed.job;
+        let error = JobError::from(error);
+        job.attempts += 1;
+        job.error = Some(error.message.clone());
+        if error.retryable && job.attempts < self.settings.max_attempts {
+            let delay = self.settings.retry_backoff.saturating_mul(1 << (job.attempts - 1).min(16));
+            let due = chrono::Utc::now().timestamp() + delay as i64;
+            let _: () = con.zadd(DELAYED, serde_json::to_string(&job)?, due).await?;
+            status::set_state(con, &job.id, "retrying", Some(&error)).await;
+        } else {
+            let _: () = con.rpush(DEAD, serde_json::to_string(&job)?).await?;
+            status::set_state(con, &job.id, "failed", Some(&error)).await;
+            finish(con, &job, &JobResult::Failed { error, castgc: Some(job.castgc.clone()) }).await?;
+        }
+        let _: () = con.lrem(&self.processing, 1, &claimed.raw).await?;
+        Ok(())
+    }
+
+    /// Moves the retries whose backoff expired back to the pending list.
+    pub async fn promote_delayed<C: AsyncCommands>(&self, con: &mut C) -> Result<(), WorkerError> {
+        let now = chrono::Utc::now().timestamp();
+        let due: Vec<String> = con.zrangebyscore_limit(DELAYED, "-inf", now, 0, 16).await?;
+        for raw in due {
+            // Only the worker that managed to remove the entry requeues it.
+            let removed: i32 = con.zrem(DELAYED, &raw).await?;
+            if removed == 1 {
+                let _: () = con.rpush(PENDING, &raw).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the jobs of workers whose heartbeat expired back to the pending
+    /// list, counting the interrupted run as a failed attempt.
+    pub async fn reclaim<C: AsyncCommands>(&self, con: &mut C) -> Result<(), WorkerError> {
+        let mut lists: Vec<String> = Vec::new();
+        let mut iter = con.scan_match::<_, String>(format!("{}*", PROCESSING_PREFIX)).await?;
+        while let Some(list) = iter.next_item().await {
+            lists.push(list);
+        }
+        drop(iter);
+        for list in lists {
+            let consumer = &list[PROCESSING_PREFIX.len()..];
+            if con.exists(format!("{}{}", CONSUMER_PREFIX, consumer)).await? {
+                continue;
+            }
+            while let Some(raw) = con.lpop::<_, Option<String>>(&list, None).await? {
+                let mut job = match serde_json::from_str::<Job>(&raw) {
+                    Ok(job) => job,
+                    Err(_) => {
+                        drop_malformed(con, &raw).await?;
+                        continue;
+                    }
+                };
+                println!("Reclaiming {} from {}", job.id, consumer);
+                let error = JobError::new(ErrorCode::Internal, "Worker stopped while processing the job".to_string());
+                job.attempts += 1;
+                job.error = Some(error.message.clone());
+                if job.attempts < self.settings.max_attempts {
+                    let _: () = con.rpush(PENDING, serde_json::to_string(&job)?).await?;
+                    status::set_state(con, &job.id, "queued", Some(&error)).await;
+                } else {
+                    let _: () = con.rpush(DEAD, serde_json::to_string(&job)?).await?;
+                    status::set_state(con, &job.id, "failed", Some(&error)).await;
+                    finish(con, &job, &JobResult::Failed { error, castgc: Some(job.castgc.clone()) }).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
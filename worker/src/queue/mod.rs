@@ -0,0 +1,2 @@
+pub mod job;
+pub mod status;
\ No newline at end of file
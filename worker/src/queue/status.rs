@@ -0,0 +1,67 @@
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use ledger_core::keys::{self, STATUS_TTL};
+use ledger_core::model::job::JobError;
+
+/// Updates the `job:{id}` hash read by `GET /jobs/{id}`. Failing to do so
+/// never fails the job itself, so errors are only logged.
+async fn update<C: AsyncCommands>(con: &mut C, id: &str, fields: &[(&str, String)]) {
+    let key = keys::job_status(id);
+    let res: Result<(), redis::RedisError> = redis::pipe()
+        .hset_multiple(&key, fields).ignore()
+        .expire(&key, STATUS_TTL).ignore()
+        .query_async(con).await;
+    if let Err(e) = res {
+        eprintln!("Failed to update the status of {}: {}", id, e);
+    }
+}
+
+pub async fn set_state<C: AsyncCommands>(con: &mut C, id: &str, state: &str, error: Option<&JobError>) {
+    let mut fields = vec![("state", state.to_string())];
+    if let Some(error) = error {
+        fields.push(("error", error.message.clone()));
+        fields.push(("error_code", error.code.as_str().to_string()));
+    }
+    if state == "finished" || state == "failed" {
+        fields.push(("finished_at", chrono::Utc::now().timestamp().to_string()));
+    }
+    update(con, id, &fields).await;
+}
+
+/// Progress reporting for the job being processed.
+pub struct Progress {
+    con: ConnectionManager,
+    id: String,
+}
+
+impl Progress {
+    pub fn new(con: ConnectionManager, id: &str) -> Self {
+        Self { con, id: id.to_string() }
+    }
+
+    pub async fn started(&self, attempts: u32) {
+        update(&mut self.con.clone(), &self.id, &[
+            ("state", "processing".to_string()),
+            ("started_at", chrono::Utc::now().timestamp().to_string()),
+            ("attempts", attempts.to_string()),
+        ]).await;
+    }
+
+    /// Records the page of `month` (`YYYYMM`) just fetched from `select.html`
+    /// and the next page announced by the card system, `0` being the last one.
+    pub async fn page(&self, month: &str, page: &str, nextpage: &str) {
+        update(&mut self.con.clone(), &self.id, &[
+            ("month", month.to_string()),
+            ("page", page.to_string()),
+            ("nextpage", nextpage.to_string()),
+        ]).await;
+    }
+
+    /// Records a previous month report generated along the way.
+    pub async fn backfilled(&self) {
+        let res: Result<(), redis::RedisError> = self.con.clone().hincr(keys::job_status(&self.id), "months_backfilled", 1).await;
+        if let Err(e) = res {
+            eprintln!("Failed to update the status of {}: {}", self.id, e);
+        }
+    }
+}
@@ -0,0 +1 @@
+pub mod transactions;
\ No newline at end of file
@@ -0,0 +1,30 @@
+use chrono::NaiveDate;
+use mongodb::Client as MongoClient;
+use ledger_core::ecard::EcardError;
+use ledger_core::store::transactions::{self, Source};
+use crate::WorkerError;
+use crate::card::session::CardSession;
+use crate::queue::status::Progress;
+
+pub use transactions::{balance_at, bounds, find};
+
+/// The card session of a job, reporting the pages fetched as its progress.
+struct JobSource<'a> {
+    card: &'a CardSession<'a>,
+    progress: &'a Progress,
+}
+
+impl Source for JobSource<'_> {
+    async fn select(&self, date_status: &str, page: u32) -> Result<serde_json::Value, EcardError> {
+        self.card.select(date_status, page).await
+    }
+
+    async fn fetched(&self, month: &str, page: u32, nextpage: &str) {
+        self.progress.page(month, &page.to_string(), nextpage).await;
+    }
+}
+
+/// Brings the local store up to date for every month touched by `from..=to`.
+pub async fn sync(card: &CardSession<'_>, account: &str, from: NaiveDate, to: NaiveDate, db: &MongoClient, progress: &Progress) -> Result<(), WorkerError> {
+    Ok(transactions::sync(&JobSource { card, progress }, account, from, to, db).await?)
+}
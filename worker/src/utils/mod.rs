@@ -1 +0,0 @@
-pub mod hust_login;
\ No newline at end of file